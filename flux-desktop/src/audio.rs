@@ -0,0 +1,50 @@
+// Feed a WAV file to the simulation in step with the animation clock.
+//
+// We don’t play the audio ourselves. Start the track in a player at the same
+// time, or mux it with a recording.
+
+use flux::audio::Wav;
+use flux::Flux;
+use std::path::Path;
+
+pub struct Track {
+    sample_rate: u32,
+    samples: Vec<f32>,
+    // The number of samples fed so far, counting each loop
+    position: u64,
+}
+
+impl Track {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let wav = Wav::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let samples = wav.to_mono();
+        if samples.is_empty() {
+            return Err(format!("{}: The WAV file is empty", path.display()));
+        }
+
+        log::info!(
+            "🎵 Loaded {:.1}s of audio from {}",
+            samples.len() as f64 / f64::from(wav.sample_rate),
+            path.display()
+        );
+
+        Ok(Self {
+            sample_rate: wav.sample_rate,
+            samples,
+            position: 0,
+        })
+    }
+
+    // Feed the samples up to `timestamp`, in milliseconds. The track loops.
+    pub fn feed_until(&mut self, flux: &mut Flux, timestamp: f64) {
+        let end = (0.001 * timestamp * f64::from(self.sample_rate)) as u64;
+        let len = self.samples.len() as u64;
+
+        while self.position < end {
+            let start = (self.position % len) as usize;
+            let count = (end - self.position).min(len - start as u64) as usize;
+            flux.feed_audio(&self.samples[start..start + count], self.sample_rate);
+            self.position += count as u64;
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: flux-desktop [OPTIONS]
+
+Options:
+  --settings <PATH>     Load settings from a JSON file and reload it on change.
+                        Defaults to $XDG_CONFIG_HOME/flux/settings.json.
+  --playlist <PATH>     Cycle through the looks in a JSON playlist.
+  --audio <PATH>        React to a WAV file, fed in step with the animation.
+                        The audio itself isn’t played.
+  --record <DIR>        Render frames offscreen instead of opening a window.
+                        Use `-` to stream frames to stdout.
+  --format <FORMAT>     Recording format: png, y4m, or rgba.
+                        Defaults to png for a directory and y4m for stdout.
+  --fps <FPS>           Virtual frame rate of the recording [default: 60]
+  --frames <N>          Number of frames to record
+  --duration <SECONDS>  Length of the recording [default: 10]
+  --size <WxH>          Logical size of the recording [default: 1280x800]
+  --scale <FACTOR>      Pixel ratio of the recording [default: 1]
+  -h, --help            Print this help
+";
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub settings_path: Option<PathBuf>,
+    pub playlist_path: Option<PathBuf>,
+    pub audio_path: Option<PathBuf>,
+    pub record: Option<RecordOptions>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordOptions {
+    pub output: RecordOutput,
+    pub format: RecordFormat,
+    pub fps: f64,
+    pub frames: u64,
+    pub logical_width: u32,
+    pub logical_height: u32,
+    pub scale_factor: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordOutput {
+    Directory(PathBuf),
+    Stdout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    Png,
+    Y4m,
+    Rgba,
+}
+
+pub enum Parsed {
+    Run(Args),
+    Help,
+}
+
+impl Args {
+    pub fn parse() -> Result<Parsed, String> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Parsed, String> {
+        let mut settings_path = None;
+        let mut playlist_path = None;
+        let mut audio_path = None;
+        let mut record = None;
+        let mut format = None;
+        let mut fps = 60.0;
+        let mut frames = None;
+        let mut duration = None;
+        let mut size = (1280, 800);
+        let mut scale_factor = 1.0;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for `{}`", arg))
+            };
+
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Parsed::Help),
+                "--settings" => settings_path = Some(PathBuf::from(value()?)),
+                "--playlist" => playlist_path = Some(PathBuf::from(value()?)),
+                "--audio" => audio_path = Some(PathBuf::from(value()?)),
+                "--record" => {
+                    record = Some(match value()?.as_str() {
+                        "-" => RecordOutput::Stdout,
+                        dir => RecordOutput::Directory(dir.into()),
+                    })
+                }
+                "--format" => format = Some(parse_format(&value()?)?),
+                "--fps" => fps = parse_positive(&arg, &value()?)?,
+                "--frames" => {
+                    let value = value()?;
+                    frames = Some(
+                        value
+                            .parse::<u64>()
+                            .ok()
+                            .filter(|&frames| frames > 0)
+                            .ok_or_else(|| format!("Invalid frame count `{}`", value))?,
+                    )
+                }
+                "--duration" => duration = Some(parse_positive(&arg, &value()?)?),
+                "--size" => size = parse_size(&value()?)?,
+                "--scale" => scale_factor = parse_positive(&arg, &value()?)?,
+                _ => return Err(format!("Unknown argument `{}`", arg)),
+            }
+        }
+
+        let record = match record {
+            Some(output) => {
+                let format = format.unwrap_or(match output {
+                    RecordOutput::Directory(_) => RecordFormat::Png,
+                    RecordOutput::Stdout => RecordFormat::Y4m,
+                });
+
+                match (&output, format) {
+                    (RecordOutput::Stdout, RecordFormat::Png) => {
+                        return Err("PNG frames can only be written to a directory".to_string())
+                    }
+                    (RecordOutput::Directory(_), RecordFormat::Y4m | RecordFormat::Rgba) => {
+                        return Err("Raw frames can only be streamed to stdout".to_string())
+                    }
+                    _ => (),
+                }
+
+                let frames =
+                    frames.unwrap_or_else(|| (duration.unwrap_or(10.0) * fps).round() as u64);
+                if frames == 0 {
+                    return Err("The recording is too short to contain a single frame".to_string());
+                }
+
+                Some(RecordOptions {
+                    output,
+                    format,
+                    fps,
+                    frames,
+                    logical_width: size.0,
+                    logical_height: size.1,
+                    scale_factor,
+                })
+            }
+            None => None,
+        };
+
+        Ok(Parsed::Run(Args {
+            settings_path,
+            playlist_path,
+            audio_path,
+            record,
+        }))
+    }
+}
+
+fn parse_format(value: &str) -> Result<RecordFormat, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "png" => Ok(RecordFormat::Png),
+        "y4m" => Ok(RecordFormat::Y4m),
+        "rgba" => Ok(RecordFormat::Rgba),
+        _ => Err(format!("Unknown recording format `{}`", value)),
+    }
+}
+
+fn parse_positive(arg: &str, value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() && number > 0.0 => Ok(number),
+        _ => Err(format!(
+            "`{}` expects a positive number, got `{}`",
+            arg, value
+        )),
+    }
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    value
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .ok_or_else(|| format!("Invalid size `{}`, expected WIDTHxHEIGHT", value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        match Args::parse_from(args.iter().map(|arg| arg.to_string()))? {
+            Parsed::Run(args) => Ok(args),
+            Parsed::Help => Err("help".to_string()),
+        }
+    }
+
+    #[test]
+    fn no_arguments_opens_a_window() {
+        let args = parse(&[]).unwrap();
+        assert_eq!(args.settings_path, None);
+        assert_eq!(args.playlist_path, None);
+        assert_eq!(args.record, None);
+    }
+
+    #[test]
+    fn reads_settings_path() {
+        let args = parse(&[
+            "--settings",
+            "flux.json",
+            "--playlist",
+            "lobby.json",
+            "--audio",
+            "set.wav",
+            "--record",
+            "out",
+        ])
+        .unwrap();
+        assert_eq!(args.settings_path, Some("flux.json".into()));
+        assert_eq!(args.playlist_path, Some("lobby.json".into()));
+        assert_eq!(args.audio_path, Some("set.wav".into()));
+        assert!(args.record.is_some());
+    }
+
+    #[test]
+    fn records_png_frames_to_a_directory() {
+        let options = parse(&["--record", "out", "--fps", "30", "--duration", "2"])
+            .unwrap()
+            .record
+            .unwrap();
+        assert_eq!(options.output, RecordOutput::Directory("out".into()));
+        assert_eq!(options.format, RecordFormat::Png);
+        assert_eq!(options.frames, 60);
+    }
+
+    #[test]
+    fn streams_y4m_to_stdout() {
+        let options = parse(&["--record", "-", "--frames", "5", "--size", "640x360"])
+            .unwrap()
+            .record
+            .unwrap();
+        assert_eq!(options.output, RecordOutput::Stdout);
+        assert_eq!(options.format, RecordFormat::Y4m);
+        assert_eq!(options.frames, 5);
+        assert_eq!((options.logical_width, options.logical_height), (640, 360));
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(parse(&["--record", "-", "--format", "png"]).is_err());
+        assert!(parse(&["--record", "out", "--format", "y4m"]).is_err());
+        assert!(parse(&["--record", "out", "--fps", "0"]).is_err());
+        assert!(parse(&["--record", "out", "--frames", "0"]).is_err());
+        assert!(parse(&["--record", "out", "--duration", "0.001"]).is_err());
+        assert!(parse(&["--record", "out", "--size", "640"]).is_err());
+        assert!(parse(&["--record"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+    }
+}
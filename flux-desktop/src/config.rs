@@ -0,0 +1,97 @@
+use flux::playlist::Playlist;
+use flux::Settings;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+// The settings file in the user’s config directory, following the XDG base
+// directory spec: `$XDG_CONFIG_HOME/flux/settings.json`, or
+// `~/.config/flux/settings.json`.
+pub fn default_settings_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            #[cfg(target_os = "windows")]
+            let home = std::env::var_os("APPDATA").map(PathBuf::from);
+            #[cfg(not(target_os = "windows"))]
+            let home = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"));
+            home
+        })?;
+
+    Some(config_dir.join("flux").join(SETTINGS_FILE_NAME))
+}
+
+pub fn load_settings(path: &Path) -> Result<Settings, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    parse_settings(&contents)
+        .map_err(|err| format!("Invalid settings in {}: {}", path.display(), err))
+}
+
+pub fn parse_settings(contents: &str) -> Result<Settings, serde_json::Error> {
+    serde_json::from_str(contents)
+}
+
+pub fn load_playlist(path: &Path) -> Result<Playlist, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    parse_playlist(&contents)
+        .map_err(|err| format!("Invalid playlist in {}: {}", path.display(), err))
+}
+
+pub fn parse_playlist(contents: &str) -> Result<Playlist, serde_json::Error> {
+    serde_json::from_str(contents)
+}
+
+// Used to poll the settings file for changes.
+pub fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn partial_settings_fall_back_to_defaults() {
+        let settings =
+            parse_settings(r#"{ "lineLength": 300.0, "viscosity": 1.5, "noiseChannels": [] }"#)
+                .unwrap();
+        assert_eq!(settings.line_length, 300.0);
+        assert_eq!(settings.viscosity, 1.5);
+        assert!(settings.noise_channels.is_empty());
+        assert_eq!(settings.grid_spacing, Settings::default().grid_spacing);
+    }
+
+    #[test]
+    fn reads_presets_and_settings_in_playlists() {
+        use flux::playlist::Look;
+
+        let playlist = parse_playlist(
+            r#"{
+                "entries": [
+                    { "preset": "Plasma", "duration": 30.0 },
+                    { "settings": { "lineLength": 300.0 } }
+                ],
+                "shuffle": true,
+                "loop": false
+            }"#,
+        )
+        .unwrap();
+        assert!(playlist.shuffle);
+        assert!(!playlist.repeat);
+        assert_eq!(playlist.entries[0].duration, Some(30.0));
+        assert!(
+            matches!(playlist.entries[1].look, Look::Settings(ref settings) if settings.line_length == 300.0)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_settings() {
+        assert!(parse_settings(r#"{ "lineLength": "long" }"#).is_err());
+    }
+}
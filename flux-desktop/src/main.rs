@@ -1,12 +1,18 @@
 // Disable the console window that pops up when you launch the .exe
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod audio;
+mod cli;
+mod config;
+mod record;
+
 use image::RgbaImage;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 
 use winit::{
-    event::{ElementState, Event, KeyEvent, WindowEvent},
+    event::{ElementState, Event, KeyEvent, MouseButton, WindowEvent},
     event_loop::EventLoop,
     keyboard::{KeyCode, PhysicalKey},
     window::WindowBuilder,
@@ -15,7 +21,8 @@ use winit::{
 #[cfg(target_os = "macos")]
 use winit::platform::macos::WindowBuilderExtMacOS;
 
-use flux::{Flux, Settings};
+use flux::playlist::Player;
+use flux::{Flux, Problem, Settings};
 
 struct App {
     runtime: tokio::runtime::Runtime,
@@ -24,27 +31,136 @@ struct App {
 
     flux: Flux,
     settings: Arc<Settings>,
+    playlist: Option<Player>,
+    track: Option<audio::Track>,
 
     color_image: Arc<Mutex<Option<RgbaImage>>>,
 }
 
+// How far a mouse drag stirs the fluid, in logical pixels.
+const DRAG_RADIUS: f32 = 60.0;
+
+// Turns mouse drags into impulses.
+#[derive(Default)]
+struct Drag {
+    dragging: bool,
+    // The last cursor position, in logical pixels, and when we saw it
+    last_position: Option<([f32; 2], std::time::Instant)>,
+}
+
+impl Drag {
+    fn set_dragging(&mut self, dragging: bool) {
+        self.dragging = dragging;
+        self.last_position = None;
+    }
+
+    // Returns the position and velocity of the cursor while dragging.
+    fn move_to(&mut self, position: [f32; 2]) -> Option<([f32; 2], [f32; 2])> {
+        if !self.dragging {
+            return None;
+        }
+
+        let now = std::time::Instant::now();
+        let previous = self.last_position.replace((position, now));
+        let ([x, y], then) = previous?;
+        let elapsed = now.duration_since(then).as_secs_f32().max(1e-3);
+        let velocity = [(position[0] - x) / elapsed, (position[1] - y) / elapsed];
+        Some((position, velocity))
+    }
+}
+
 enum Msg {
     DecodedImage,
+    LoadedPalette(flux::settings::Palette),
+    UpdatedSettings(Arc<Settings>),
+    DeviceLost(Problem),
 }
 
 impl App {
-    fn handle_pending_messages(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+    fn handle_pending_messages(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<(), Problem> {
         while let Ok(msg) = self.rx.try_recv() {
             match msg {
                 Msg::DecodedImage => {
                     if let Some(image) = &*self.color_image.lock().unwrap() {
-                        self.flux.sample_colors_from_image(device, queue, image);
+                        if let Err(err) = self.flux.sample_colors_from_image(device, queue, image) {
+                            log::error!("{}", err);
+                        }
+                    }
+                }
+                Msg::LoadedPalette(palette) => {
+                    self.flux
+                        .sample_colors_from_palette(device, queue, &palette);
+                }
+                Msg::UpdatedSettings(settings) => {
+                    // The playlist’s presets apply on top of the settings file.
+                    let settings = match &mut self.playlist {
+                        Some(player) => Arc::clone(player.set_base_settings(settings)),
+                        None => settings,
+                    };
+                    match self.flux.update(device, queue, &settings) {
+                        Ok(()) => {
+                            log::info!("🔧 Reloaded settings");
+                            self.settings = settings;
+                        }
+                        Err(err) => log::error!("{}", err),
+                    }
+                }
+                Msg::DeviceLost(problem) => return Err(problem),
+            }
+        }
+
+        Ok(())
+    }
+
+    // Move on to the next playlist entry when it’s time.
+    fn play(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, timestamp: f64) {
+        if let Some(player) = &mut self.playlist {
+            if let Some(settings) = player.tick(timestamp) {
+                let settings = Arc::clone(settings);
+                let entry = player.current_entry();
+                match self.flux.update(device, queue, &settings) {
+                    Ok(()) => {
+                        log::info!("▶️ Playing playlist entry {}", entry);
+                        self.settings = settings;
                     }
+                    Err(err) => log::error!("{}", err),
                 }
             }
         }
     }
 
+    // Load a dropped palette or image file.
+    pub fn open_file(&self, path: PathBuf) {
+        if flux::palette::Format::from_path(&path).is_none() {
+            match std::fs::read(&path) {
+                Ok(bytes) => self.decode_image(bytes),
+                Err(err) => log::error!("Failed to read {}: {}", path.display(), err),
+            }
+            return;
+        }
+
+        let tx = self.tx.clone();
+        self.runtime.spawn(async move {
+            match flux::palette::read(&path) {
+                Ok(palette) => {
+                    log::info!(
+                        "🎨 Loaded {} colors from {}",
+                        palette.stops.len(),
+                        path.display()
+                    );
+                    if tx.send(Msg::LoadedPalette(palette)).await.is_err() {
+                        log::error!("Failed to send palette message");
+                    }
+                }
+                Err(err) => log::error!("{}: {}", path.display(), err),
+            }
+        });
+    }
+
     pub fn decode_image(&self, encoded_bytes: Vec<u8>) {
         let tx = self.tx.clone();
         let color_image = Arc::clone(&self.color_image);
@@ -64,11 +180,94 @@ impl App {
         });
         log::debug!("Spawned image decoding task");
     }
+
+    // Poll the settings file for changes. The file doesn’t have to exist yet.
+    pub fn watch_settings(&self, path: PathBuf) {
+        log::debug!("Watching {} for changes", path.display());
+        let tx = self.tx.clone();
+        self.runtime.spawn(async move {
+            let mut last_modified = config::modified_time(&path);
+            let mut interval = tokio::time::interval(std::time::Duration::from_millis(500));
+
+            loop {
+                interval.tick().await;
+
+                let modified = config::modified_time(&path);
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+
+                if modified.is_none() {
+                    continue;
+                }
+
+                match config::load_settings(&path) {
+                    Ok(settings) => {
+                        if tx
+                            .send(Msg::UpdatedSettings(Arc::new(settings)))
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                    Err(msg) => log::error!("{}", msg),
+                }
+            }
+        });
+    }
 }
 
 fn main() -> Result<(), impl std::error::Error> {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
+    let args = match cli::Args::parse() {
+        Ok(cli::Parsed::Run(args)) => args,
+        Ok(cli::Parsed::Help) => {
+            print!("{}", cli::USAGE);
+            std::process::exit(0);
+        }
+        Err(msg) => {
+            eprintln!("{}\n\n{}", msg, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // An explicit settings file must exist. The one in the config directory is
+    // optional.
+    let settings_path = args
+        .settings_path
+        .clone()
+        .or_else(config::default_settings_path);
+    let settings = match (&args.settings_path, &settings_path) {
+        (Some(path), _) => config::load_settings(path),
+        (None, Some(path)) if path.exists() => config::load_settings(path),
+        _ => Ok(Settings::default()),
+    };
+    let settings = match settings {
+        Ok(settings) => Arc::new(settings),
+        Err(msg) => exit_with_error(msg),
+    };
+
+    let playlist = args.playlist_path.as_ref().map(|path| {
+        let playlist = config::load_playlist(path).unwrap_or_else(|msg| exit_with_error(msg));
+        Player::new(playlist, Arc::clone(&settings))
+            .unwrap_or_else(|err| exit_with_error(format!("{}: {}", path.display(), err)))
+    });
+
+    let track = args
+        .audio_path
+        .as_ref()
+        .map(|path| audio::Track::load(path).unwrap_or_else(|msg| exit_with_error(msg)));
+
+    if let Some(options) = args.record {
+        if let Err(msg) = pollster::block_on(record::run(&options, &settings, playlist, track)) {
+            exit_with_error(msg);
+        }
+        return Ok(());
+    }
+
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .worker_threads(1)
         .enable_all()
@@ -99,14 +298,31 @@ fn main() -> Result<(), impl std::error::Error> {
         .build(&event_loop)
         .unwrap();
 
-    pollster::block_on(run(runtime, event_loop, window))
+    pollster::block_on(run(
+        runtime,
+        event_loop,
+        window,
+        settings,
+        settings_path,
+        playlist,
+        track,
+    ))
 }
 
 async fn run(
     runtime: tokio::runtime::Runtime,
     event_loop: EventLoop<()>,
     window: winit::window::Window,
+    settings: Arc<Settings>,
+    settings_path: Option<PathBuf>,
+    playlist: Option<Player>,
+    track: Option<audio::Track>,
 ) -> Result<(), impl std::error::Error> {
+    let settings = match &playlist {
+        Some(player) => Arc::clone(player.settings()),
+        None => settings,
+    };
+
     let wgpu_instance = wgpu::Instance::default();
     let window_surface = wgpu_instance.create_surface(&window).unwrap();
     let adapter = wgpu_instance
@@ -116,7 +332,7 @@ async fn run(
             compatible_surface: Some(&window_surface),
         })
         .await
-        .expect("Failed to find an appropiate adapter");
+        .unwrap_or_else(|| exit_with_error(Problem::NoAdapter));
 
     // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
     let mut limits = wgpu::Limits::default().using_resolution(adapter.limits());
@@ -127,6 +343,10 @@ async fn run(
         | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
         | wgpu::Features::FLOAT32_FILTERABLE;
 
+    if let Err(err) = Flux::check_adapter(&adapter, features, &limits) {
+        exit_with_error(err);
+    }
+
     let (device, command_queue) = adapter
         .request_device(
             &wgpu::DeviceDescriptor {
@@ -138,7 +358,7 @@ async fn run(
             None,
         )
         .await
-        .expect("Failed to create device");
+        .unwrap_or_else(|err| exit_with_error(Problem::from(err)));
 
     let swapchain_capabilities = window_surface.get_capabilities(&adapter);
     let swapchain_format = get_preferred_format(&swapchain_capabilities);
@@ -159,7 +379,6 @@ async fn run(
     window_surface.configure(&device, &config);
 
     let logical_size = physical_size.to_logical(window.scale_factor());
-    let settings = Arc::new(Settings::default());
     let flux = Flux::new(
         &device,
         &command_queue,
@@ -170,7 +389,7 @@ async fn run(
         physical_size.height,
         &Arc::clone(&settings),
     )
-    .unwrap();
+    .unwrap_or_else(|err| exit_with_error(err));
 
     window.set_visible(true);
 
@@ -181,15 +400,31 @@ async fn run(
         rx,
         flux,
         settings,
+        playlist,
+        track,
         color_image: Arc::new(Mutex::new(None)),
     };
 
+    if let Some(path) = settings_path {
+        app.watch_settings(path);
+    }
+
+    let device_lost_tx = app.tx.clone();
+    Flux::on_device_lost(&device, move |problem| {
+        let _ = device_lost_tx.try_send(Msg::DeviceLost(problem));
+    });
+
     let start = std::time::Instant::now();
+    let mut drag = Drag::default();
 
     event_loop.run(|event, elwt| {
         elwt.set_control_flow(winit::event_loop::ControlFlow::Poll);
 
-        app.handle_pending_messages(&device, &command_queue);
+        if let Err(err) = app.handle_pending_messages(&device, &command_queue) {
+            log::error!("{}", err);
+            elwt.exit();
+            return;
+        }
 
         match event {
             Event::AboutToWait => {
@@ -206,9 +441,19 @@ async fn run(
                         },
                     ..
                 } => elwt.exit(),
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Left,
+                    ..
+                } => drag.set_dragging(state == ElementState::Pressed),
+                WindowEvent::CursorMoved { position, .. } => {
+                    let position = position.to_logical::<f32>(window.scale_factor());
+                    if let Some((position, velocity)) = drag.move_to([position.x, position.y]) {
+                        app.flux.add_impulse(position, velocity, DRAG_RADIUS);
+                    }
+                }
                 WindowEvent::DroppedFile(path) => {
-                    let bytes = std::fs::read(path).unwrap();
-                    app.decode_image(bytes);
+                    app.open_file(path);
                     window.request_redraw();
                 }
                 WindowEvent::Resized(new_size) => {
@@ -239,13 +484,18 @@ async fn run(
                             label: Some("flux:render"),
                         });
 
+                    let timestamp = start.elapsed().as_secs_f64() * 1000.0;
+                    app.play(&device, &command_queue, timestamp);
+                    if let Some(track) = &mut app.track {
+                        track.feed_until(&mut app.flux, timestamp);
+                    }
                     app.flux.animate(
                         &device,
                         &command_queue,
                         &mut encoder,
                         &view,
                         None,
-                        start.elapsed().as_secs_f64() * 1000.0,
+                        timestamp,
                     );
 
                     command_queue.submit(Some(encoder.finish()));
@@ -259,6 +509,11 @@ async fn run(
     })
 }
 
+fn exit_with_error(err: impl std::fmt::Display) -> ! {
+    log::error!("{}", err);
+    std::process::exit(1);
+}
+
 fn get_preferred_format(capabilities: &wgpu::SurfaceCapabilities) -> wgpu::TextureFormat {
     // Prefer non-srgb formats, as we will be doing linear math in the shaders.
     // If the swapchain doesn't support any non-srgb formats, we will fall back to srgb.
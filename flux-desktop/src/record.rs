@@ -0,0 +1,162 @@
+// Record frames offscreen at a fixed, virtual frame rate.
+//
+// The simulation runs in deterministic mode, advancing by one frame interval per
+// frame, so the output doesn’t depend on how long each frame takes to render.
+// With a seed, the same settings record the same frames.
+
+use crate::audio::Track;
+use crate::cli::{RecordFormat, RecordOptions, RecordOutput};
+
+use flux::playlist::Player;
+use flux::{Headless, Settings};
+use image::RgbaImage;
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+
+pub async fn run(
+    options: &RecordOptions,
+    settings: &Arc<Settings>,
+    mut playlist: Option<Player>,
+    mut track: Option<Track>,
+) -> Result<(), String> {
+    let settings = match &playlist {
+        Some(player) => player.settings(),
+        None => settings,
+    };
+
+    let (device, queue) = Headless::request_device(false)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let physical_width = (options.scale_factor * f64::from(options.logical_width)) as u32;
+    let physical_height = (options.scale_factor * f64::from(options.logical_height)) as u32;
+
+    let mut headless = Headless::new(
+        &device,
+        &queue,
+        options.logical_width,
+        options.logical_height,
+        physical_width,
+        physical_height,
+        settings,
+    )
+    .map_err(|err| err.to_string())?;
+    headless
+        .flux_mut()
+        .set_deterministic_timestep(Some((1.0 / options.fps) as f32));
+
+    let mut sink = FrameSink::new(options, physical_width, physical_height)?;
+
+    log::info!(
+        "🎥 Recording {} frames at {} fps ({}x{})",
+        options.frames,
+        options.fps,
+        physical_width,
+        physical_height
+    );
+
+    for frame_index in 0..options.frames {
+        let timestamp = frame_index as f64 * 1000.0 / options.fps;
+        if let Some(settings) = playlist.as_mut().and_then(|player| player.tick(timestamp)) {
+            headless
+                .update(&device, &queue, settings)
+                .map_err(|err| err.to_string())?;
+        }
+        if let Some(track) = &mut track {
+            track.feed_until(headless.flux_mut(), timestamp);
+        }
+        let frame = headless.animate(&device, &queue, timestamp);
+        sink.write_frame(frame_index, &frame)
+            .map_err(|err| format!("Failed to write frame {}: {}", frame_index, err))?;
+    }
+
+    sink.finish()
+        .map_err(|err| format!("Failed to finish recording: {}", err))?;
+
+    log::info!("🎬 Done");
+
+    Ok(())
+}
+
+enum FrameSink {
+    Png(std::path::PathBuf),
+    Stream {
+        format: RecordFormat,
+        writer: BufWriter<io::Stdout>,
+    },
+}
+
+impl FrameSink {
+    fn new(options: &RecordOptions, width: u32, height: u32) -> Result<Self, String> {
+        match &options.output {
+            RecordOutput::Directory(dir) => {
+                std::fs::create_dir_all(dir)
+                    .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+                Ok(FrameSink::Png(dir.clone()))
+            }
+            RecordOutput::Stdout => {
+                let mut writer = BufWriter::new(io::stdout());
+                if options.format == RecordFormat::Y4m {
+                    write_y4m_header(&mut writer, width, height, options.fps)
+                        .map_err(|err| format!("Failed to write Y4M header: {}", err))?;
+                }
+                Ok(FrameSink::Stream {
+                    format: options.format,
+                    writer,
+                })
+            }
+        }
+    }
+
+    fn write_frame(&mut self, frame_index: u64, frame: &RgbaImage) -> io::Result<()> {
+        match self {
+            FrameSink::Png(dir) => frame
+                .save(dir.join(format!("frame_{:06}.png", frame_index)))
+                .map_err(io::Error::other),
+            FrameSink::Stream { format, writer } => match format {
+                RecordFormat::Y4m => write_y4m_frame(writer, frame),
+                _ => writer.write_all(frame.as_raw()),
+            },
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            FrameSink::Png(_) => Ok(()),
+            FrameSink::Stream { mut writer, .. } => writer.flush(),
+        }
+    }
+}
+
+fn write_y4m_header(writer: &mut impl Write, width: u32, height: u32, fps: f64) -> io::Result<()> {
+    // Express the frame rate as a fraction to support rates like 29.97.
+    let (numerator, denominator) = if fps.fract() == 0.0 {
+        (fps as u64, 1)
+    } else {
+        ((fps * 1000.0).round() as u64, 1000)
+    };
+
+    writeln!(
+        writer,
+        "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+        width, height, numerator, denominator
+    )
+}
+
+// Write a full-resolution, planar frame using limited-range BT.601.
+fn write_y4m_frame(writer: &mut impl Write, frame: &RgbaImage) -> io::Result<()> {
+    let pixel_count = (frame.width() * frame.height()) as usize;
+    let mut planes = vec![0u8; 3 * pixel_count];
+    let (y_plane, chroma_planes) = planes.split_at_mut(pixel_count);
+    let (u_plane, v_plane) = chroma_planes.split_at_mut(pixel_count);
+
+    for (i, pixel) in frame.pixels().enumerate() {
+        let [r, g, b, _] = pixel.0.map(f32::from);
+        y_plane[i] = (16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8;
+        u_plane[i] = (128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8;
+        v_plane[i] = (128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8;
+    }
+
+    writer.write_all(b"FRAME\n")?;
+    writer.write_all(&planes)
+}
@@ -1,3 +1,4 @@
+use flux::playlist::{Player, Playlist};
 use flux::{self, settings};
 use gloo_utils::format::JsValueSerdeExt;
 use std::sync::Arc;
@@ -20,15 +21,64 @@ pub struct Flux {
     logical_height: u32,
     pixel_ratio: f64,
     instance: flux::Flux,
+    settings: Arc<settings::Settings>,
+    playlist: Option<Player>,
 }
 
 #[wasm_bindgen]
 impl Flux {
     #[wasm_bindgen(setter)]
-    pub fn set_settings(&mut self, settings_object: &JsValue) {
-        let settings: settings::Settings = settings_object.into_serde().unwrap();
+    pub fn set_settings(&mut self, settings_object: &JsValue) -> Result<(), JsValue> {
+        let settings: settings::Settings = settings_object
+            .into_serde()
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        let settings = Arc::new(settings);
+
+        // The playlist’s presets apply on top of these settings.
+        let current_settings = match &mut self.playlist {
+            Some(player) => Arc::clone(player.set_base_settings(Arc::clone(&settings))),
+            None => Arc::clone(&settings),
+        };
         self.instance
-            .update(&self.device, &self.queue, &Arc::new(settings));
+            .update(&self.device, &self.queue, &current_settings)
+            .map_err(to_js_error)?;
+
+        self.settings = settings;
+        Ok(())
+    }
+
+    // Cycle through a playlist of looks. Set to `null` to go back to the
+    // settings.
+    #[wasm_bindgen(setter)]
+    pub fn set_playlist(&mut self, playlist_object: &JsValue) -> Result<(), JsValue> {
+        if playlist_object.is_null() || playlist_object.is_undefined() {
+            self.playlist = None;
+            return self
+                .instance
+                .update(&self.device, &self.queue, &self.settings)
+                .map_err(to_js_error);
+        }
+
+        let playlist: Playlist = playlist_object
+            .into_serde()
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        let player = Player::new(playlist, Arc::clone(&self.settings))
+            .map_err(|err| to_js_error(err.into()))?;
+        self.instance
+            .update(&self.device, &self.queue, player.settings())
+            .map_err(to_js_error)?;
+
+        self.playlist = Some(player);
+        Ok(())
+    }
+
+    // Use an encoded image, like a PNG, for the obstacle in the settings.
+    pub fn set_obstacle_image(&mut self, encoded_bytes: &[u8]) -> Result<(), JsValue> {
+        let image = flux::render::obstacle::decode_image(encoded_bytes)
+            .map_err(|err| to_js_error(err.into()))?;
+        self.instance
+            .set_obstacle_image(&self.device, &self.queue, image);
+        Ok(())
     }
 
     #[wasm_bindgen]
@@ -134,7 +184,8 @@ impl Flux {
                 compatible_surface: Some(&window_surface),
             })
             .await
-            .expect("Failed to find an appropiate adapter");
+            .ok_or(flux::Problem::NoAdapter)
+            .map_err(to_js_error)?;
 
         log::debug!("{:?}\n{:?}", adapter.get_info(), adapter.features(),);
 
@@ -144,6 +195,8 @@ impl Flux {
         let features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
             | wgpu::Features::FLOAT32_FILTERABLE;
 
+        flux::Flux::check_adapter(&adapter, features, &limits).map_err(to_js_error)?;
+
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
@@ -155,7 +208,9 @@ impl Flux {
                 None,
             )
             .await
-            .expect("Failed to create device");
+            .map_err(|err| to_js_error(err.into()))?;
+
+        flux::Flux::on_device_lost(&device, |problem| log::error!("{}", problem));
 
         let swapchain_capabilities = window_surface.get_capabilities(&adapter);
         let swapchain_format = swapchain_capabilities.formats[0];
@@ -184,10 +239,12 @@ impl Flux {
             physical_height,
             &settings,
         )
-        .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        .map_err(to_js_error)?;
 
         Ok(Self {
             instance: flux,
+            settings,
+            playlist: None,
             canvas,
             device,
             queue,
@@ -200,6 +257,14 @@ impl Flux {
     }
 
     pub fn animate(&mut self, timestamp: f64) {
+        if let Some(player) = &mut self.playlist {
+            if let Some(settings) = player.tick(timestamp) {
+                if let Err(err) = self.instance.update(&self.device, &self.queue, settings) {
+                    log::error!("{}", err);
+                }
+            }
+        }
+
         let frame = self
             .window_surface
             .get_current_texture()
@@ -226,6 +291,20 @@ impl Flux {
         frame.present();
     }
 
+    // Stir the fluid from a pointer or touch. Positions and the radius are in
+    // CSS pixels, relative to the canvas, and the velocity is in CSS pixels per
+    // second.
+    pub fn add_impulse(&mut self, x: f32, y: f32, velocity_x: f32, velocity_y: f32, radius: f32) {
+        self.instance
+            .add_impulse([x, y], [velocity_x, velocity_y], radius);
+    }
+
+    // Listen to audio, like a microphone or a playing track. Pass mono samples
+    // in [-1, 1] as they arrive.
+    pub fn feed_audio(&mut self, samples: &[f32], sample_rate: u32) {
+        self.instance.feed_audio(samples, sample_rate);
+    }
+
     pub fn resize(&mut self, logical_width: u32, logical_height: u32) {
         if (self.logical_width != logical_width) || (self.logical_height != logical_height) {
             let (physical_width, physical_height) =
@@ -305,6 +384,30 @@ impl Canvas {
     }
 }
 
+// Convert a problem into a JS error. The error’s `name` identifies the kind of
+// failure.
+fn to_js_error(problem: flux::Problem) -> JsValue {
+    use flux::Problem::*;
+    let name = match problem {
+        InvalidSettings(_) => "InvalidSettings",
+        ColorImage(_) => "ColorImage",
+        Obstacle(_) => "Obstacle",
+        Palette(_) => "Palette",
+        Playlist(_) => "Playlist",
+        Snapshot(_) => "Snapshot",
+        NoAdapter => "NoAdapter",
+        MissingFeatures(_) | MissingLimit { .. } | UnsupportedTextureFormat { .. } => {
+            "UnsupportedAdapter"
+        }
+        RequestDevice(_) => "RequestDevice",
+        DeviceLost { .. } => "DeviceLost",
+    };
+
+    let error = js_sys::Error::new(&problem.to_string());
+    error.set_name(name);
+    error.into()
+}
+
 pub fn window() -> Window {
     web_sys::window().expect("The global `window` doesn’t exist")
 }
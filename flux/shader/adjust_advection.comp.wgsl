@@ -1,14 +1,5 @@
 // include fluid.inc
-struct FluidUniforms {
-  timestep: f32,
-  dissipation: f32,
-  alpha: f32,
-  r_beta: f32,
-  center_factor: f32,
-  stencil_factor: f32,
-}
 
-@group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
 @group(0) @binding(1) var linear_sampler: sampler;
 @group(0) @binding(2) var nearest_sampler: sampler;
 
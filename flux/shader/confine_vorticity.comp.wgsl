@@ -0,0 +1,53 @@
+// Vorticity confinement
+//
+// The advection and the diffusion smear out small eddies, especially on coarse
+// grids. This pushes the fluid around each eddy in the direction it already
+// spins, to put back some of the lost detail.
+//
+// The force points along N × ω, where N is the unit vector towards the nearest
+// peak in the magnitude of the vorticity ω.
+//
+// Fedkiw, Stam and Jensen, Visual Simulation of Smoke, 2001.
+
+// include fluid.inc
+
+@group(1) @binding(0) var vorticity_texture: texture_2d<f32>;
+
+@group(2) @binding(0) var velocity_texture: texture_2d<f32>;
+@group(2) @binding(1) var out_velocity_texture: texture_storage_2d<rg32float, write>;
+
+// The magnitude of a neighbouring vorticity. The vorticity is already zero
+// inside obstacles.
+fn load_magnitude(cell: vec2<i32>, size: vec2<i32>) -> f32 {
+  var neighbour = clamp(cell, vec2<i32>(0), size - 1);
+  if (uniforms.boundary == PERIODIC) {
+    neighbour = (cell + size) % size;
+  }
+  return abs(textureLoad(vorticity_texture, neighbour, 0).x);
+}
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = vec2<i32>(textureDimensions(velocity_texture));
+  let cell = vec2<i32>(global_id.xy);
+
+  let l = load_magnitude(cell - vec2<i32>(1, 0), size);
+  let r = load_magnitude(cell + vec2<i32>(1, 0), size);
+  let b = load_magnitude(cell - vec2<i32>(0, 1), size);
+  let t = load_magnitude(cell + vec2<i32>(0, 1), size);
+
+  let gradient = 0.5 * vec2<f32>(r - l, t - b);
+  // Avoid dividing by zero where the vorticity is flat.
+  let direction = gradient / (length(gradient) + 1e-5);
+
+  let vorticity = textureLoad(vorticity_texture, cell, 0).x;
+  let force = uniforms.vorticity_strength * vorticity * vec2<f32>(direction.y, -direction.x);
+
+  let velocity = textureLoad(velocity_texture, cell, 0).xy;
+  let new_velocity = velocity + uniforms.timestep * force;
+
+  textureStore(out_velocity_texture, cell, vec4<f32>(new_velocity, 0.0, 0.0));
+}
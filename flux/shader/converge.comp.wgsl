@@ -0,0 +1,50 @@
+// Skip the rest of the pressure solve once the residual is small enough.
+//
+// The solver dispatches its passes indirectly, with one set of workgroup
+// counts per multigrid level. `reset` fills them in at the start of a solve.
+// `decide` runs after measure_residual.comp.wgsl, and zeroes them once the
+// largest residual drops below the tolerance.
+
+struct ConvergenceUniforms {
+  tolerance: f32,
+  level_count: u32,
+  padding0: u32,
+  padding1: u32,
+  workgroups: array<vec4<u32>, 8>,
+}
+
+struct Residual {
+  max: atomic<u32>,
+}
+
+struct DispatchArgs {
+  x: u32,
+  y: u32,
+  z: u32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: ConvergenceUniforms;
+@group(0) @binding(1) var<storage, read_write> residual: Residual;
+@group(0) @binding(2) var<storage, read_write> dispatches: array<DispatchArgs>;
+
+@compute
+@workgroup_size(1)
+fn reset() {
+  atomicStore(&residual.max, 0u);
+  for (var i = 0u; i < uniforms.level_count; i++) {
+    let workgroups = uniforms.workgroups[i];
+    dispatches[i] = DispatchArgs(workgroups.x, workgroups.y, 1u);
+  }
+}
+
+@compute
+@workgroup_size(1)
+fn decide() {
+  let max_residual = bitcast<f32>(atomicLoad(&residual.max));
+  if (max_residual < uniforms.tolerance) {
+    for (var i = 0u; i < uniforms.level_count; i++) {
+      dispatches[i] = DispatchArgs(0u, 0u, 0u);
+    }
+  }
+  atomicStore(&residual.max, 0u);
+}
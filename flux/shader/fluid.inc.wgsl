@@ -1,5 +1,91 @@
+// The uniforms and boundary handling that the fluid shaders share.
+//
+// Shaders pull this in with a `// include fluid.inc` line, which
+// `render::fluid::include_fluid` replaces with this file. Keep FluidUniforms in
+// sync with the Rust side in render/fluid.rs.
+
 struct FluidUniforms {
   timestep: f32,
   dissipation: f32,
-  texel_size: vec2<f32>,
+  alpha: f32,
+  r_beta: f32,
+  center_factor: f32,
+  stencil_factor: f32,
+  boundary: u32,
+  vorticity_strength: f32,
+}
+
+// Settings::boundary
+const CLOSED: u32 = 0u;
+const FREE_SLIP: u32 = 1u;
+const OPEN: u32 = 2u;
+const PERIODIC: u32 = 3u;
+
+@group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
+@group(0) @binding(3) var obstacle_texture: texture_2d<f32>;
+
+// Whether a cell lies inside an obstacle. The obstacle mask covers the fluid,
+// and coarser grids look it up at their cell centers.
+fn is_solid(cell: vec2<i32>, size: vec2<i32>) -> bool {
+  let mask_size = vec2<i32>(textureDimensions(obstacle_texture));
+  return textureLoad(obstacle_texture, ((2 * cell + 1) * mask_size) / (2 * size), 0).x > 0.5;
+}
+
+// Read a neighbouring velocity. Closed walls hold the velocity outside the
+// grid at zero, and obstacles hold it at zero inside them.
+fn load_velocity(velocity_texture: texture_2d<f32>, cell: vec2<i32>, size: vec2<i32>) -> vec2<f32> {
+  let is_outside = any(cell < vec2<i32>(0)) || any(cell >= size);
+  if (is_outside && uniforms.boundary == CLOSED) {
+    return vec2<f32>(0.0);
+  }
+
+  var neighbour = clamp(cell, vec2<i32>(0), size - 1);
+  if (uniforms.boundary == PERIODIC) {
+    neighbour = (cell + size) % size;
+  }
+  if (is_solid(neighbour, size)) {
+    return vec2<f32>(0.0);
+  }
+  return textureLoad(velocity_texture, neighbour, 0).xy;
+}
+
+// Read a neighbouring pressure. Outside the grid, walls mirror the cell itself
+// for a pure Neumann condition, and open edges hold the pressure at zero.
+//
+// Obstacles mirror the cell too. Cells inside an obstacle read their
+// neighbours as usual, which smooths the pressure around the obstacle into it
+// and keeps the gradient at its surface in check.
+fn load_pressure(
+  pressure_texture: texture_2d<f32>,
+  cell: vec2<i32>,
+  size: vec2<i32>,
+  pressure: f32,
+  is_fluid: bool,
+) -> f32 {
+  let is_outside = any(cell < vec2<i32>(0)) || any(cell >= size);
+  if (is_outside && uniforms.boundary != PERIODIC) {
+    return select(pressure, 0.0, uniforms.boundary == OPEN);
+  }
+
+  let neighbour = (cell + size) % size;
+  if (is_fluid && is_solid(neighbour, size)) {
+    return pressure;
+  }
+  return textureLoad(pressure_texture, neighbour, 0).x;
+}
+
+// The left, right, bottom and top neighbouring pressures
+fn get_neighbours(
+  pressure_texture: texture_2d<f32>,
+  cell: vec2<i32>,
+  size: vec2<i32>,
+  pressure: f32,
+) -> vec4<f32> {
+  let is_fluid = !is_solid(cell, size);
+  return vec4<f32>(
+    load_pressure(pressure_texture, cell - vec2<i32>(1, 0), size, pressure, is_fluid),
+    load_pressure(pressure_texture, cell + vec2<i32>(1, 0), size, pressure, is_fluid),
+    load_pressure(pressure_texture, cell - vec2<i32>(0, 1), size, pressure, is_fluid),
+    load_pressure(pressure_texture, cell + vec2<i32>(0, 1), size, pressure, is_fluid),
+  );
 }
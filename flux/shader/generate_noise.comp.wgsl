@@ -8,9 +8,23 @@ struct Channel {
   offset_2: f32,
   blend_factor: f32,
   multiplier: f32,
-  padding: vec2<f32>,
+  kind: u32,
+  octaves: u32,
+  lacunarity: f32,
+  gain: f32,
+  warp: f32,
+  padding: u32,
 }
 
+// Settings::NoiseKind
+const SIMPLEX: u32 = 0u;
+const CURL: u32 = 1u;
+const FBM: u32 = 2u;
+const DOMAIN_WARPED: u32 = 3u;
+
+// settings::MAX_NOISE_OCTAVES
+const MAX_OCTAVES: u32 = 8u;
+
 @group(0) @binding(0) var<uniform> uniforms: NoiseUniforms;
 @group(0) @binding(1) var<storage, read> channels: array<Channel>;
 @group(0) @binding(2) var out_texture: texture_storage_2d<rg32float, write>;
@@ -95,13 +109,71 @@ fn make_noise_pair(params: vec3<f32>) -> vec2<f32> {
   return vec2(snoise(params), snoise(params + vec3(8.0, -8.0, 0.0)));
 }
 
-fn make_noise(texel_position: vec2<f32>, channel: Channel) -> vec2<f32> {
+// The curl of a simplex noise potential, from central differences.
+//
+// The noise is stretched to fit the texture, so scale the derivatives to keep
+// the flow divergence-free on the texture’s grid of texels.
+fn make_curl_noise(position: vec2<f32>, offset: f32, channel: Channel, size: vec2<f32>) -> vec2<f32> {
+  let epsilon = 0.01;
+  let dx = vec3(epsilon, 0.0, 0.0);
+  let dy = vec3(0.0, epsilon, 0.0);
+  let params = vec3(position, offset);
+  let gradient = vec2(
+    snoise(params + dx) - snoise(params - dx),
+    snoise(params + dy) - snoise(params - dy),
+  ) / (2.0 * epsilon);
+
+  let stretch = (channel.scale.x * size.y) / (channel.scale.y * size.x);
+  return vec2(gradient.y, -stretch * gradient.x);
+}
+
+// Add up octaves of noise pairs, and normalize the sum back to the range of a
+// single octave.
+fn make_fbm_noise(position: vec2<f32>, offset: f32, channel: Channel) -> vec2<f32> {
+  var noise = vec2(0.0);
+  var frequency = 1.0;
+  var amplitude = 1.0;
+  var total_amplitude = 0.0;
+  for (var octave = 0u; octave < min(channel.octaves, MAX_OCTAVES); octave++) {
+    // Shift each octave, so that they don’t all line up at the origin.
+    let shift = 17.0 * f32(octave);
+    noise += amplitude * make_noise_pair(vec3(frequency * position + shift, offset));
+    total_amplitude += amplitude;
+    frequency *= channel.lacunarity;
+    amplitude *= channel.gain;
+  }
+  return noise / max(total_amplitude, 1e-6);
+}
+
+fn make_domain_warped_noise(position: vec2<f32>, offset: f32, channel: Channel) -> vec2<f32> {
+  let warp = make_noise_pair(vec3(position + vec2(5.2, 1.3), offset));
+  return make_noise_pair(vec3(position + channel.warp * warp, offset));
+}
+
+fn make_noise_of_kind(position: vec2<f32>, offset: f32, channel: Channel, size: vec2<f32>) -> vec2<f32> {
+  switch channel.kind {
+    case CURL: {
+      return make_curl_noise(position, offset, channel, size);
+    }
+    case FBM: {
+      return make_fbm_noise(position, offset, channel);
+    }
+    case DOMAIN_WARPED: {
+      return make_domain_warped_noise(position, offset, channel);
+    }
+    case SIMPLEX, default: {
+      return make_noise_pair(vec3(position, offset));
+    }
+  }
+}
+
+fn make_noise(texel_position: vec2<f32>, channel: Channel, size: vec2<f32>) -> vec2<f32> {
   let scale = channel.scale * texel_position;
-  let noise1 = make_noise_pair(vec3(scale, channel.offset_1));
+  let noise1 = make_noise_of_kind(scale, channel.offset_1, channel, size);
   var noise = noise1;
 
   if (channel.blend_factor > 0.0) {
-    let noise2 = make_noise_pair(vec3(scale, channel.offset_2));
+    let noise2 = make_noise_of_kind(scale, channel.offset_2, channel, size);
     noise = mix(noise1, noise2, channel.blend_factor);
   }
 
@@ -125,7 +197,7 @@ fn main(
     }
 
     let channel = channels[i];
-    noise += make_noise(texel_position, channel);
+    noise += make_noise(texel_position, channel, size);
 
     continuing {
       i = i + 1u;
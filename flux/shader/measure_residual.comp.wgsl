@@ -0,0 +1,45 @@
+// Find the largest residual of the pressure solve. See residual.comp.wgsl.
+
+// include fluid.inc
+
+struct Residual {
+  // The bits of a non-negative float, which sort like the float itself
+  max: atomic<u32>,
+}
+
+@group(1) @binding(0) var divergence_texture: texture_2d<f32>;
+
+@group(2) @binding(0) var pressure_texture: texture_2d<f32>;
+
+@group(3) @binding(0) var<storage, read_write> residual: Residual;
+
+var<workgroup> workgroup_max: atomic<u32>;
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+  @builtin(local_invocation_index) local_index: u32,
+) {
+  if (local_index == 0u) {
+    atomicStore(&workgroup_max, 0u);
+  }
+  workgroupBarrier();
+
+  let size = textureDimensions(pressure_texture);
+  let cell = vec2<i32>(global_id.xy);
+  // The pressure inside obstacles doesn’t need to converge.
+  if (all(global_id.xy < size) && !is_solid(cell, vec2<i32>(size))) {
+    let pressure = textureLoad(pressure_texture, cell, 0).x;
+    let divergence = textureLoad(divergence_texture, cell, 0).x;
+    let n = get_neighbours(pressure_texture, cell, vec2<i32>(size), pressure);
+    let value = divergence + (n.x + n.y + n.z + n.w - pressure / uniforms.r_beta) / uniforms.alpha;
+    atomicMax(&workgroup_max, bitcast<u32>(abs(value)));
+  }
+  workgroupBarrier();
+
+  // One global atomic per workgroup
+  if (local_index == 0u) {
+    atomicMax(&residual.max, atomicLoad(&workgroup_max));
+  }
+}
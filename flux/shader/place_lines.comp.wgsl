@@ -20,18 +20,33 @@ struct LineUniforms {
   line_noise_blend_factor: f32,
   color_mode: u32,
   delta_time: f32,
+  color_interpolation: u32,
+  previous_color_mode: u32,
+  previous_color_interpolation: u32,
+  color_blend: f32,
+}
+
+struct LineColor {
+  color: vec3<f32>,
+  momentum_boost: f32,
+  delta_boost: f32,
 }
 
 @group(0) @binding(0) var<uniform> uniforms: LineUniforms;
 @group(0) @binding(1) var<storage, read> basepoints: array<vec2<f32>>;
 @group(0) @binding(2) var linear_sampler: sampler;
 @group(0) @binding(3) var color_texture_sampler: sampler;
+// 1 inside obstacles, 0 elsewhere
+@group(0) @binding(4) var obstacle_texture: texture_2d<f32>;
 
 @group(1) @binding(0) var<storage, read> lines: array<Line>;
 @group(1) @binding(1) var<storage, read_write> out_lines: array<Line>;
 
 @group(2) @binding(0) var color_texture: texture_2d<f32>;
 @group(2) @binding(1) var<storage, read> color_buffer: array<vec4<f32>>;
+// The colors to fade from after the colors change
+@group(2) @binding(2) var previous_color_texture: texture_2d<f32>;
+@group(2) @binding(3) var<storage, read> previous_color_buffer: array<vec4<f32>>;
 
 @group(3) @binding(0) var velocity_texture: texture_2d<f32>;
 
@@ -139,40 +154,42 @@ fn main(
   // Basically, smoothstep(0.0, 0.4, length(velocity));
   // Maybe width and opacity should be on different easings.
   let width_boost = saturate(2.5 * length(velocity));
-  let new_line_width = width_boost * width_boost * (3.0 - width_boost * 2.0);
 
-  var color: vec3<f32>;
-  var color_momentum_boost = 3.0;
-  var color_delta_boost = 90.0;
+  // Hide the lines inside obstacles. The velocity is zero in there, so the
+  // lines shrink too.
+  let visibility = 1.0 - textureSampleLevel(obstacle_texture, linear_sampler, basepoint, 0.0).x;
+  let new_line_width = visibility * width_boost * width_boost * (3.0 - width_boost * 2.0);
 
-  switch uniforms.color_mode {
-    // Original
-    case 0u, default: {
-      color = vec3<f32>(saturate(vec2<f32>(1.0, 0.66) * (0.5 + velocity)), 0.5);
-    }
-
-    // Color wheel
-    case 1u: {
-      let angle = atan2(velocity.y, velocity.x);
-      color = get_color(angle + pi, tau).rgb;
-      // Using the velocity length instead of the angle
-      // color = get_color(2.0 * length(velocity), 1.3).rgb;
-    }
+  var line_color = get_line_color(
+    current_colors,
+    uniforms.color_mode,
+    uniforms.color_interpolation,
+    velocity,
+  );
 
-    case 2u: {
-      color = textureSampleLevel(color_texture, color_texture_sampler, 2.0 * velocity + 0.5, 0.0).rgb;
-      color_momentum_boost = 5.0;
-      color_delta_boost = 10.0;
-    }
+  // Crossfade from the previous colors
+  if (uniforms.color_blend < 1.0) {
+    let previous_line_color = get_line_color(
+      previous_colors,
+      uniforms.previous_color_mode,
+      uniforms.previous_color_interpolation,
+      velocity,
+    );
+    let t = uniforms.color_blend;
+    line_color = LineColor(
+      mix(previous_line_color.color, line_color.color, t),
+      mix(previous_line_color.momentum_boost, line_color.momentum_boost, t),
+      mix(previous_line_color.delta_boost, line_color.delta_boost, t),
+    );
   }
 
   let new_color_velocity
-    = line.color_velocity * (1.0 - color_momentum_boost * uniforms.delta_time)
-    + (color.rgb - line.color.rgb) * color_delta_boost * uniforms.delta_time;
+    = line.color_velocity * (1.0 - line_color.momentum_boost * uniforms.delta_time)
+    + (line_color.color - line.color.rgb) * line_color.delta_boost * uniforms.delta_time;
 
   let new_color = vec4(
     saturate(line.color.rgb + uniforms.delta_time * new_color_velocity),
-    width_boost,
+    visibility * width_boost,
     // TODO: expose options?
     // smoothstep(0.05, 0.7, length(velocity)),
   );
@@ -189,17 +206,146 @@ fn main(
 const pi = 3.141592653589793;
 const tau = 2.0 * pi;
 
-// Get a color from the ring buffer of colors.
+// Which of the color bindings to read from
+const current_colors = 0u;
+const previous_colors = 1u;
+
+fn get_line_color(
+  colors: u32,
+  color_mode: u32,
+  interpolation: u32,
+  velocity: vec2<f32>,
+) -> LineColor {
+  switch color_mode {
+    // Original
+    case 0u, default: {
+      let color = vec3<f32>(saturate(vec2<f32>(1.0, 0.66) * (0.5 + velocity)), 0.5);
+      return LineColor(color, 3.0, 90.0);
+    }
+
+    // Color wheel
+    case 1u: {
+      let angle = atan2(velocity.y, velocity.x);
+      let color = get_color(colors, interpolation, angle + pi, tau);
+      // Using the velocity length instead of the angle
+      // let color = get_color(colors, interpolation, 2.0 * length(velocity), 1.3);
+      return LineColor(color, 3.0, 90.0);
+    }
+
+    case 2u: {
+      let uv = 2.0 * velocity + 0.5;
+      var color: vec3<f32>;
+      if (colors == current_colors) {
+        color = textureSampleLevel(color_texture, color_texture_sampler, uv, 0.0).rgb;
+      } else {
+        color = textureSampleLevel(previous_color_texture, color_texture_sampler, uv, 0.0).rgb;
+      }
+      return LineColor(color, 5.0, 10.0);
+    }
+  }
+}
+
+fn get_color_stop_count(colors: u32) -> u32 {
+  if (colors == current_colors) {
+    return arrayLength(&color_buffer);
+  }
+  return arrayLength(&previous_color_buffer);
+}
+
+fn get_color_stop(colors: u32, index: u32) -> vec4<f32> {
+  if (colors == current_colors) {
+    return color_buffer[index];
+  }
+  return previous_color_buffer[index];
+}
+
+// Get a color from the ring of color stops. Each stop stores its position in
+// [0, 1) in the w component, and the stops are sorted by position.
 // Limit specifies the value at which the color should wrap around.
-fn get_color(value: f32, limit: f32) -> vec4<f32> {
-  let size = f32(arrayLength(&color_buffer));
-  let slice = limit / size;
-  let raw_index = (value % limit) / slice;
-  let index = floor(raw_index);
-  let next_index = (index + 1.0) % size;
-  let interpolate = fract(raw_index);
-
-  let current_color = color_buffer[u32(index)];
-  let next_color = color_buffer[u32(next_index)];
-  return mix(current_color, next_color, interpolate);
+fn get_color(colors: u32, interpolation: u32, value: f32, limit: f32) -> vec3<f32> {
+  let size = get_color_stop_count(colors);
+  let position = fract(value / limit);
+
+  // Find the last stop at or before the position. Positions before the first
+  // stop wrap around to the last one.
+  var index = size - 1u;
+  for (var i = 0u; i < size; i++) {
+    if (get_color_stop(colors, i).w <= position) {
+      index = i;
+    }
+  }
+  let next_index = (index + 1u) % size;
+
+  let current_stop = get_color_stop(colors, index);
+  let next_stop = get_color_stop(colors, next_index);
+  let span = fract(next_stop.w - current_stop.w);
+  let interpolate = select(0.0, saturate(fract(position - current_stop.w) / span), span > 0.0);
+
+  return mix_colors(interpolation, current_stop.rgb, next_stop.rgb, interpolate);
+}
+
+fn mix_colors(interpolation: u32, a: vec3<f32>, b: vec3<f32>, t: f32) -> vec3<f32> {
+  switch interpolation {
+    // sRGB
+    case 0u, default: {
+      return mix(a, b, t);
+    }
+
+    // Linear RGB
+    case 1u: {
+      return linear_to_srgb(mix(srgb_to_linear(a), srgb_to_linear(b), t));
+    }
+
+    // OKLab
+    case 2u: {
+      let lab_a = linear_srgb_to_oklab(srgb_to_linear(a));
+      let lab_b = linear_srgb_to_oklab(srgb_to_linear(b));
+      return linear_to_srgb(saturate(oklab_to_linear_srgb(mix(lab_a, lab_b, t))));
+    }
+  }
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+  let low = color / 12.92;
+  let high = pow((color + 0.055) / 1.055, vec3(2.4));
+  return select(high, low, color <= vec3(0.04045));
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+  let low = color * 12.92;
+  let high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+  return select(high, low, color <= vec3(0.0031308));
+}
+
+// https://bottosson.github.io/posts/oklab/
+fn linear_srgb_to_oklab(c: vec3<f32>) -> vec3<f32> {
+  let l = 0.4122214708 * c.r + 0.5363325363 * c.g + 0.0514459929 * c.b;
+  let m = 0.2119034982 * c.r + 0.6806995451 * c.g + 0.1073969566 * c.b;
+  let s = 0.0883024619 * c.r + 0.2817188376 * c.g + 0.6299787005 * c.b;
+
+  let l_ = pow(max(l, 0.0), 1.0 / 3.0);
+  let m_ = pow(max(m, 0.0), 1.0 / 3.0);
+  let s_ = pow(max(s, 0.0), 1.0 / 3.0);
+
+  return vec3(
+    0.2104542553 * l_ + 0.7936177850 * m_ - 0.0040720468 * s_,
+    1.9779984951 * l_ - 2.4285922050 * m_ + 0.4505937099 * s_,
+    0.0259040371 * l_ + 0.7827717662 * m_ - 0.8086757660 * s_,
+  );
+}
+
+fn oklab_to_linear_srgb(c: vec3<f32>) -> vec3<f32> {
+  let l_ = c.x + 0.3963377774 * c.y + 0.2158037573 * c.z;
+  let m_ = c.x - 0.1055613458 * c.y - 0.0638541728 * c.z;
+  let s_ = c.x - 0.0894841775 * c.y - 1.2914855480 * c.z;
+
+  let l = l_ * l_ * l_;
+  let m = m_ * m_ * m_;
+  let s = s_ * s_ * s_;
+
+  return vec3(
+     4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+    -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+    -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+  );
 }
@@ -0,0 +1,25 @@
+// Add the correction from the coarser grid back onto the pressure.
+
+@group(0) @binding(1) var linear_sampler: sampler;
+
+@group(1) @binding(0) var correction_texture: texture_2d<f32>;
+
+@group(2) @binding(0) var pressure_texture: texture_2d<f32>;
+@group(2) @binding(1) var out_pressure_texture: texture_storage_2d<r32float, write>;
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = textureDimensions(pressure_texture);
+  if (global_id.x >= size.x || global_id.y >= size.y) {
+    return;
+  }
+
+  let sample_position = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size);
+  let pressure = textureLoad(pressure_texture, global_id.xy, 0).x;
+  let correction = textureSampleLevel(correction_texture, linear_sampler, sample_position, 0.0).x;
+
+  textureStore(out_pressure_texture, global_id.xy, vec4<f32>(pressure + correction, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,60 @@
+// Map the lines of the previous grid onto the basepoints of a new grid.
+//
+// Both grids span the unit square, so each new basepoint falls between four
+// old lines. Interpolate their state, so that the lines keep their shape and
+// color when the grid changes.
+
+struct Line {
+  endpoint: vec2<f32>,
+  velocity: vec2<f32>,
+  color: vec4<f32>,
+  color_velocity: vec3<f32>,
+  width: f32,
+}
+
+struct ResampleUniforms {
+  // The columns and rows of the previous grid
+  grid_size: vec2<u32>,
+  line_count: u32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: ResampleUniforms;
+@group(0) @binding(1) var<storage, read> basepoints: array<vec2<f32>>;
+
+@group(1) @binding(0) var<storage, read> lines: array<Line>;
+@group(1) @binding(1) var<storage, read_write> out_lines: array<Line>;
+
+fn get_line(cell: vec2<u32>) -> Line {
+  return lines[cell.y * uniforms.grid_size.x + cell.x];
+}
+
+fn mix_lines(a: Line, b: Line, t: f32) -> Line {
+  return Line(
+    mix(a.endpoint, b.endpoint, t),
+    mix(a.velocity, b.velocity, t),
+    mix(a.color, b.color, t),
+    mix(a.color_velocity, b.color_velocity, t),
+    mix(a.width, b.width, t),
+  );
+}
+
+@compute
+@workgroup_size(64)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let index = global_id.x;
+  if (index >= uniforms.line_count) {
+    return;
+  }
+
+  let last_cell = uniforms.grid_size - 1u;
+  let position = clamp(basepoints[index], vec2(0.0), vec2(1.0)) * vec2<f32>(last_cell);
+  let cell = min(vec2<u32>(floor(position)), last_cell);
+  let next_cell = min(cell + 1u, last_cell);
+  let t = position - vec2<f32>(cell);
+
+  let bottom = mix_lines(get_line(cell), get_line(vec2(next_cell.x, cell.y)), t.x);
+  let top = mix_lines(get_line(vec2(cell.x, next_cell.y)), get_line(next_cell), t.x);
+  out_lines[index] = mix_lines(bottom, top, t.y);
+}
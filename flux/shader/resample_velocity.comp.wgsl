@@ -0,0 +1,24 @@
+// Resample the velocity field into a texture of a different size.
+//
+// The velocities aren’t rescaled. The lines read them directly, so rescaling
+// would change the line lengths.
+
+@group(0) @binding(1) var linear_sampler: sampler;
+
+@group(1) @binding(0) var velocity_texture: texture_2d<f32>;
+@group(1) @binding(1) var out_velocity_texture: texture_storage_2d<rg32float, write>;
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = textureDimensions(out_velocity_texture);
+  if (global_id.x >= size.x || global_id.y >= size.y) {
+    return;
+  }
+
+  let sample_position = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size);
+  let velocity = textureSampleLevel(velocity_texture, linear_sampler, sample_position, 0.0).xy;
+  textureStore(out_velocity_texture, global_id.xy, vec4<f32>(velocity, 0.0, 0.0));
+}
@@ -0,0 +1,34 @@
+// How far each cell is from solving the pressure equation, in units of
+// divergence. The multigrid solver carries this down to the coarser grids.
+
+// include fluid.inc
+
+@group(1) @binding(0) var divergence_texture: texture_2d<f32>;
+
+@group(2) @binding(0) var pressure_texture: texture_2d<f32>;
+
+@group(3) @binding(0) var out_residual_texture: texture_storage_2d<r32float, write>;
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = textureDimensions(pressure_texture);
+  if (global_id.x >= size.x || global_id.y >= size.y) {
+    return;
+  }
+
+  let cell = vec2<i32>(global_id.xy);
+  let pressure = textureLoad(pressure_texture, cell, 0).x;
+  let divergence = textureLoad(divergence_texture, cell, 0).x;
+  let n = get_neighbours(pressure_texture, cell, vec2<i32>(size), pressure);
+  var residual = divergence + (n.x + n.y + n.z + n.w - pressure / uniforms.r_beta) / uniforms.alpha;
+
+  // The pressure inside obstacles doesn’t need to converge.
+  if (is_solid(cell, vec2<i32>(size))) {
+    residual = 0.0;
+  }
+
+  textureStore(out_residual_texture, cell, vec4<f32>(residual, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,34 @@
+// Carry the residual down to a grid of half the size, as the right-hand side
+// of the coarse pressure equation.
+//
+// The coarse grid spacing is twice as large, which scales the Laplacian by a
+// quarter. Summing the four fine cells, instead of averaging them, makes up
+// for that, so the coarse grid can use the same stencil.
+//
+// The coarse pressure, which solves for the correction, starts from zero.
+
+@group(0) @binding(0) var residual_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var out_divergence_texture: texture_storage_2d<r32float, write>;
+@group(1) @binding(1) var out_pressure_texture: texture_storage_2d<r32float, write>;
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = textureDimensions(out_divergence_texture);
+  if (global_id.x >= size.x || global_id.y >= size.y) {
+    return;
+  }
+
+  let cell = 2 * vec2<i32>(global_id.xy);
+  let residual
+    = textureLoad(residual_texture, cell, 0).x
+    + textureLoad(residual_texture, cell + vec2<i32>(1, 0), 0).x
+    + textureLoad(residual_texture, cell + vec2<i32>(0, 1), 0).x
+    + textureLoad(residual_texture, cell + vec2<i32>(1, 1), 0).x;
+
+  textureStore(out_divergence_texture, global_id.xy, vec4<f32>(residual, 0.0, 0.0, 0.0));
+  textureStore(out_pressure_texture, global_id.xy, vec4<f32>(0.0));
+}
@@ -0,0 +1,42 @@
+// Half of a red-black Gauss–Seidel iteration.
+//
+// Update the cells where x + y has the given parity, and copy the rest. The
+// second half-sweep then reads the values that the first one just wrote.
+
+// include fluid.inc
+
+struct RedBlackUniforms {
+  parity: u32,
+  padding0: u32,
+  padding1: u32,
+  padding2: u32,
+}
+
+@group(1) @binding(0) var divergence_texture: texture_2d<f32>;
+
+@group(2) @binding(0) var pressure_texture: texture_2d<f32>;
+@group(2) @binding(1) var out_pressure_texture: texture_storage_2d<r32float, write>;
+
+@group(3) @binding(0) var<uniform> red_black: RedBlackUniforms;
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = textureDimensions(pressure_texture);
+  if (global_id.x >= size.x || global_id.y >= size.y) {
+    return;
+  }
+
+  let cell = vec2<i32>(global_id.xy);
+  var pressure = textureLoad(pressure_texture, cell, 0).x;
+
+  if ((global_id.x + global_id.y) % 2u == red_black.parity) {
+    let n = get_neighbours(pressure_texture, cell, vec2<i32>(size), pressure);
+    let divergence = textureLoad(divergence_texture, cell, 0).x;
+    pressure = uniforms.r_beta * (n.x + n.y + n.z + n.w + uniforms.alpha * divergence);
+  }
+
+  textureStore(out_pressure_texture, cell, vec4<f32>(pressure, 0.0, 0.0, 0.0));
+}
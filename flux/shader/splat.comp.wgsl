@@ -0,0 +1,50 @@
+// Stir the fluid with Gaussian velocity splats.
+//
+// Each impulse pulls the velocity around its position towards its own
+// velocity. The pull falls off with distance, so repeated splats along a drag
+// don’t pile up into runaway velocities.
+
+struct ImpulseUniforms {
+  count: u32,
+  padding0: u32,
+  padding1: u32,
+  padding2: u32,
+}
+
+struct Impulse {
+  // In normalized texture coordinates
+  position: vec2<f32>,
+  velocity: vec2<f32>,
+  // The radius along each axis, in normalized texture coordinates
+  radius: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: ImpulseUniforms;
+@group(0) @binding(1) var<storage, read> impulses: array<Impulse>;
+
+@group(1) @binding(0) var velocity_texture: texture_2d<f32>;
+@group(1) @binding(1) var out_velocity_texture: texture_storage_2d<rg32float, write>;
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = textureDimensions(out_velocity_texture);
+  if (global_id.x >= size.x || global_id.y >= size.y) {
+    return;
+  }
+
+  let position = (vec2<f32>(global_id.xy) + 0.5) / vec2<f32>(size);
+  var velocity = textureLoad(velocity_texture, global_id.xy, 0).xy;
+
+  let count = min(uniforms.count, arrayLength(&impulses));
+  for (var i = 0u; i < count; i++) {
+    let impulse = impulses[i];
+    let offset = (position - impulse.position) / impulse.radius;
+    let falloff = exp(-dot(offset, offset));
+    velocity = mix(velocity, impulse.velocity, falloff);
+  }
+
+  textureStore(out_velocity_texture, global_id.xy, vec4<f32>(velocity, 0.0, 0.0));
+}
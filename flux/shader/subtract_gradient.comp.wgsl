@@ -1,14 +1,5 @@
 // include fluid.inc
-struct FluidUniforms {
-  timestep: f32,
-  dissipation: f32,
-  alpha: f32,
-  r_beta: f32,
-  center_factor: f32,
-  stencil_factor: f32,
-}
 
-@group(0) @binding(0) var<uniform> uniforms: FluidUniforms;
 @group(0) @binding(1) var linear_sampler: sampler;
 @group(0) @binding(2) var nearest_sampler: sampler;
 
@@ -17,6 +8,16 @@ struct FluidUniforms {
 @group(2) @binding(0) var velocity_texture: texture_2d<f32>;
 @group(2) @binding(1) var out_velocity_texture: texture_storage_2d<rg32float, write>;
 
+// Whether a neighbouring cell lies inside an obstacle. Outside the grid, the
+// boundary takes over.
+fn is_solid_neighbour(cell: vec2<i32>, size: vec2<i32>) -> bool {
+  let is_outside = any(cell < vec2<i32>(0)) || any(cell >= size);
+  if (is_outside && uniforms.boundary != PERIODIC) {
+    return false;
+  }
+  return is_solid((cell + size) % size, size);
+}
+
 @compute
 @workgroup_size(16, 16, 1)
 fn main(
@@ -25,16 +26,15 @@ fn main(
   let size = textureDimensions(velocity_texture);
   let sample_position = vec2<f32>(global_id.xy) / vec2<f32>(size);
 
-  let pressure = textureLoad(pressure_texture, global_id.xy, 0).x;
-
   var l = textureSampleLevel(pressure_texture, linear_sampler, sample_position, 0.0, vec2<i32>(-1, 0)).x;
   var r = textureSampleLevel(pressure_texture, linear_sampler, sample_position, 0.0, vec2<i32>(1, 0)).x;
   var b = textureSampleLevel(pressure_texture, linear_sampler, sample_position, 0.0, vec2<i32>(0, -1)).x;
   var t = textureSampleLevel(pressure_texture, linear_sampler, sample_position, 0.0, vec2<i32>(0, 1)).x;
 
-  // Enforce the following boundary conditions:
+  // The free-slip boundary enforces the following conditions:
   //
-  //  1. No-slip condition — velocity equals zero at the boundaries.
+  //  1. No-penetration condition — the velocity normal to the boundary equals
+  //     zero.
   //
   //  2. Pure Neumann pressure condition — dp/dn = 0, that is the rate of change
   //     of pressure in the direction normal to the boundary is zero.
@@ -44,12 +44,17 @@ fn main(
   //
   //  Here, we’re assuming the boundary is the outer edge of the texture grid.
   //
-  //  For condition 1, we just set the velocity to zero.
+  //  For condition 1, we just set the normal velocity to zero.
   //
   //  For condition 2, we don’t have to do anything. With texture clamping, any
   //  pressure reads outside the boundary will be set to the last value at the
   //  boundary; so the rate of change across the boundary becomes zero.
   //
+  //  A closed boundary also stops the tangential velocity, so that the fluid
+  //  sticks to the walls. An open boundary leaves the velocity alone, and reads
+  //  zero pressure outside the grid, so the fluid can flow out. A periodic
+  //  boundary wraps around through the sampler.
+  //
   //  I haven’t tested this with an ink/particle texture, so there’s a chance
   //  this doesn’t actually look any good. But it is stable! I’m also unsure of
   //  how the staggered grid affects all of this.
@@ -58,16 +63,46 @@ fn main(
   //  setting just the relevant component of velocity to zero, and flipping
   //  pressures along relevant axis. All seem stable, but experiment!
 
+  let is_edge = vec2<bool>(
+    global_id.x == 0u || global_id.x == size.x - 1u,
+    global_id.y == 0u || global_id.y == size.y - 1u,
+  );
+
+  if (uniforms.boundary == OPEN) {
+    if (global_id.x == 0u) {
+      l = 0.0;
+    }
+    if (global_id.x == size.x - 1u) {
+      r = 0.0;
+    }
+    if (global_id.y == 0u) {
+      b = 0.0;
+    }
+    if (global_id.y == size.y - 1u) {
+      t = 0.0;
+    }
+  }
+
   var boundary_condition = vec2<f32>(1.0);
-  if (global_id.x == 0u) {
-    boundary_condition.x = 0.0;
-  } else if (global_id.x == size.x - 1u) {
-    boundary_condition.x = 0.0;
+  if (uniforms.boundary == CLOSED && any(is_edge)) {
+    boundary_condition = vec2<f32>(0.0);
+  } else if (uniforms.boundary == FREE_SLIP) {
+    boundary_condition = select(vec2<f32>(1.0), vec2<f32>(0.0), is_edge);
   }
-  if (global_id.y == 0u) {
-    boundary_condition.y = 0.0;
-  } else if (global_id.y == size.y - 1u) {
-    boundary_condition.y = 0.0;
+
+  // Obstacles act like free-slip walls: the fluid slides along them, but not
+  // into them. Inside, the fluid stands still.
+  let cell = vec2<i32>(global_id.xy);
+  let cell_count = vec2<i32>(size);
+  let is_next_to_obstacle = vec2<bool>(
+    is_solid_neighbour(cell - vec2<i32>(1, 0), cell_count)
+      || is_solid_neighbour(cell + vec2<i32>(1, 0), cell_count),
+    is_solid_neighbour(cell - vec2<i32>(0, 1), cell_count)
+      || is_solid_neighbour(cell + vec2<i32>(0, 1), cell_count),
+  );
+  boundary_condition = select(boundary_condition, vec2<f32>(0.0), is_next_to_obstacle);
+  if (is_solid(cell, cell_count)) {
+    boundary_condition = vec2<f32>(0.0);
   }
 
   let velocity = textureLoad(velocity_texture, global_id.xy, 0).xy;
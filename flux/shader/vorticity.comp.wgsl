@@ -0,0 +1,28 @@
+// include fluid.inc
+
+@group(1) @binding(0) var out_vorticity_texture: texture_storage_2d<r32float, write>;
+
+@group(2) @binding(0) var velocity_texture: texture_2d<f32>;
+@group(2) @binding(1) var out_velocity_texture: texture_storage_2d<rg32float, write>;
+
+// The curl of the velocity. Positive values spin counter-clockwise.
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+  @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+  let size = vec2<i32>(textureDimensions(velocity_texture));
+  let cell = vec2<i32>(global_id.xy);
+
+  let l = load_velocity(velocity_texture, cell - vec2<i32>(1, 0), size).y;
+  let r = load_velocity(velocity_texture, cell + vec2<i32>(1, 0), size).y;
+  let t = load_velocity(velocity_texture, cell + vec2<i32>(0, 1), size).x;
+  let b = load_velocity(velocity_texture, cell - vec2<i32>(0, 1), size).x;
+
+  var new_vorticity = 0.5 * ((r - l) - (t - b));
+  if (is_solid(cell, size)) {
+    new_vorticity = 0.0;
+  }
+
+  textureStore(out_vorticity_texture, cell, vec4<f32>(new_vorticity, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,538 @@
+// Follow music: band levels and onsets from a stream of PCM samples.
+//
+// The analyzer cuts the stream into overlapping frames and looks at the
+// spectrum of each one. The band levels follow the energy in each frequency
+// band, measured against the recent peak, so that quiet and loud tracks both
+// use the whole range. Onsets are sudden jumps in the spectrum, like drum hits.
+//
+// The mappings in the audio settings turn the levels into multipliers for the
+// noise, the line width and the color transitions.
+//
+// Samples come from the host, either pushed from a live source or decoded from
+// a WAV file with `Wav`.
+
+use crate::settings::{self, AudioSource, AudioTarget};
+use crate::snapshot::{self, AudioState};
+
+use std::f32::consts::TAU;
+use std::path::Path;
+
+// The number of samples in each analysis frame. Must be a power of two.
+pub const FRAME_SIZE: usize = 1024;
+// Frames overlap by half.
+const HOP_SIZE: usize = FRAME_SIZE / 2;
+
+// How long it takes the peak of a band to fall back after a loud passage, in
+// seconds.
+const PEAK_RELEASE: f32 = 5.0;
+// Bands quieter than this count as silent, instead of being boosted to full
+// level.
+const SILENCE: f32 = 1e-4;
+// How long the average spectral flux remembers, in seconds.
+const FLUX_AVERAGE_TIME: f32 = 0.5;
+// Ignore changes in the spectrum below this.
+const MIN_FLUX: f32 = 0.01;
+// The shortest time between two onsets, in seconds.
+const MIN_ONSET_INTERVAL: f32 = 0.1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Problem {
+    #[error("Failed to read audio: {0}")]
+    Read(#[from] std::io::Error),
+
+    #[error("Not a WAV file")]
+    NotWav,
+
+    #[error("The WAV file is missing its `{0}` chunk")]
+    MissingChunk(&'static str),
+
+    #[error("The WAV file ends unexpectedly")]
+    Truncated,
+
+    #[error("Unsupported WAV format: {format} with {bits} bits per sample")]
+    UnsupportedFormat { format: u16, bits: u16 },
+}
+
+// Decoded PCM audio.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: u16,
+    // Interleaved samples in [-1, 1]
+    pub samples: Vec<f32>,
+}
+
+impl Wav {
+    pub fn read(path: &Path) -> Result<Self, Problem> {
+        Self::decode(&std::fs::read(path)?)
+    }
+
+    // Decode integer or float PCM from a RIFF WAVE file.
+    pub fn decode(bytes: &[u8]) -> Result<Self, Problem> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(Problem::NotWav);
+        }
+
+        let mut format = None;
+        let mut data = None;
+        let mut rest = &bytes[12..];
+        while rest.len() >= 8 {
+            let id = &rest[0..4];
+            let size = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            let body = rest.get(8..8 + size).ok_or(Problem::Truncated)?;
+            match id {
+                b"fmt " => format = Some(body),
+                b"data" => data = Some(body),
+                _ => (),
+            }
+            // Chunks are padded to an even size.
+            rest = rest.get(8 + size + size % 2..).unwrap_or(&[]);
+        }
+
+        let format = format.ok_or(Problem::MissingChunk("fmt "))?;
+        let data = data.ok_or(Problem::MissingChunk("data"))?;
+        if format.len() < 16 {
+            return Err(Problem::Truncated);
+        }
+
+        let read_u16 = |offset: usize| u16::from_le_bytes([format[offset], format[offset + 1]]);
+        let mut format_tag = read_u16(0);
+        let channels = read_u16(2);
+        let sample_rate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+        let bits = read_u16(14);
+
+        // WAVE_FORMAT_EXTENSIBLE keeps the actual format in its subformat GUID.
+        if format_tag == 0xFFFE && format.len() >= 26 {
+            format_tag = read_u16(24);
+        }
+
+        let to_sample: fn(&[u8]) -> f32 = match (format_tag, bits) {
+            (1, 8) => |b| (f32::from(b[0]) - 128.0) / 128.0,
+            (1, 16) => |b| f32::from(i16::from_le_bytes([b[0], b[1]])) / 32768.0,
+            (1, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8388608.0,
+            (1, 32) => |b| i32::from_le_bytes(b.try_into().unwrap()) as f32 / 2147483648.0,
+            (3, 32) => |b| f32::from_le_bytes(b.try_into().unwrap()),
+            (3, 64) => |b| f64::from_le_bytes(b.try_into().unwrap()) as f32,
+            (format, bits) => return Err(Problem::UnsupportedFormat { format, bits }),
+        };
+        if channels == 0 || sample_rate == 0 {
+            return Err(Problem::UnsupportedFormat {
+                format: format_tag,
+                bits,
+            });
+        }
+
+        let samples = data
+            .chunks_exact(usize::from(bits / 8))
+            .map(to_sample)
+            .collect();
+
+        Ok(Self {
+            sample_rate,
+            channels,
+            samples,
+        })
+    }
+
+    // Average the channels together.
+    pub fn to_mono(&self) -> Vec<f32> {
+        let channels = usize::from(self.channels);
+        self.samples
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Levels {
+    // One level per band, in [0, 1]
+    pub bands: Vec<f32>,
+    // 1 on an onset, fading to 0
+    pub onset: f32,
+}
+
+impl Levels {
+    pub fn get(&self, source: AudioSource) -> f32 {
+        match source {
+            AudioSource::Band(index) => self.bands.get(index).copied().unwrap_or(0.0),
+            AudioSource::Onset => self.onset,
+        }
+    }
+}
+
+pub struct Analyzer {
+    sample_rate: u32,
+    settings: settings::Audio,
+
+    window: Vec<f32>,
+    // Samples waiting for a full frame
+    pending: Vec<f32>,
+    previous_spectrum: Vec<f32>,
+
+    peaks: Vec<f32>,
+    flux_average: f32,
+    time_since_onset: f32,
+    levels: Levels,
+}
+
+impl Analyzer {
+    pub fn new(sample_rate: u32, settings: &settings::Audio) -> Self {
+        // A Hann window
+        let window = (0..FRAME_SIZE)
+            .map(|i| 0.5 - 0.5 * (TAU * i as f32 / FRAME_SIZE as f32).cos())
+            .collect();
+
+        Self {
+            sample_rate,
+            settings: settings.clone(),
+            window,
+            pending: Vec::with_capacity(2 * FRAME_SIZE),
+            previous_spectrum: vec![0.0; FRAME_SIZE / 2 + 1],
+            peaks: vec![0.0; settings.bands.len()],
+            flux_average: 0.0,
+            time_since_onset: MIN_ONSET_INTERVAL,
+            levels: Levels {
+                bands: vec![0.0; settings.bands.len()],
+                onset: 0.0,
+            },
+        }
+    }
+
+    // Pick up new audio settings. Changing the bands starts them over.
+    pub fn update(&mut self, settings: &settings::Audio) {
+        if settings.bands != self.settings.bands {
+            self.peaks = vec![0.0; settings.bands.len()];
+            self.levels.bands = vec![0.0; settings.bands.len()];
+        }
+
+        self.settings = settings.clone();
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn get_levels(&self) -> &Levels {
+        &self.levels
+    }
+
+    pub(crate) fn snapshot(&self) -> AudioState {
+        AudioState {
+            sample_rate: self.sample_rate,
+            pending: self.pending.clone(),
+            previous_spectrum: self.previous_spectrum.clone(),
+            peaks: self.peaks.clone(),
+            flux_average: self.flux_average,
+            time_since_onset: self.time_since_onset,
+            bands: self.levels.bands.clone(),
+            onset: self.levels.onset,
+        }
+    }
+
+    // An analyzer that picks up where the snapshot left off.
+    pub(crate) fn restore(
+        state: &AudioState,
+        settings: &settings::Audio,
+    ) -> Result<Self, snapshot::Problem> {
+        snapshot::check(
+            "number of audio bands",
+            state.bands.len(),
+            settings.bands.len(),
+        )?;
+        snapshot::check(
+            "number of audio peaks",
+            state.peaks.len(),
+            settings.bands.len(),
+        )?;
+        snapshot::check(
+            "audio spectrum size",
+            state.previous_spectrum.len(),
+            FRAME_SIZE / 2 + 1,
+        )?;
+
+        let mut analyzer = Self::new(state.sample_rate, settings);
+        analyzer.pending.clone_from(&state.pending);
+        analyzer
+            .previous_spectrum
+            .clone_from(&state.previous_spectrum);
+        analyzer.peaks.clone_from(&state.peaks);
+        analyzer.flux_average = state.flux_average;
+        analyzer.time_since_onset = state.time_since_onset;
+        analyzer.levels = Levels {
+            bands: state.bands.clone(),
+            onset: state.onset,
+        };
+        Ok(analyzer)
+    }
+
+    // Analyze mono samples. The levels update once per hop, so a handful of
+    // samples may not change them.
+    pub fn feed(&mut self, samples: &[f32]) {
+        self.pending.extend_from_slice(samples);
+
+        while self.pending.len() >= FRAME_SIZE {
+            self.analyze_frame();
+            self.pending.drain(..HOP_SIZE);
+        }
+    }
+
+    fn analyze_frame(&mut self) {
+        let timestep = HOP_SIZE as f32 / self.sample_rate as f32;
+
+        let mut re = self.pending[..FRAME_SIZE]
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, weight)| sample * weight)
+            .collect::<Vec<_>>();
+        let mut im = vec![0.0; FRAME_SIZE];
+        fft(&mut re, &mut im);
+
+        // Scaled so that a full-scale sine peaks at about 1.
+        let spectrum = re[..=FRAME_SIZE / 2]
+            .iter()
+            .zip(&im)
+            .map(|(re, im)| (re * re + im * im).sqrt() * 4.0 / FRAME_SIZE as f32)
+            .collect::<Vec<_>>();
+
+        let peak_decay = (-timestep / PEAK_RELEASE).exp();
+        for (index, band) in self.settings.bands.iter().enumerate() {
+            let bins = self.bins(*band);
+            let energy = (spectrum[bins.clone()].iter().map(|m| m * m).sum::<f32>()
+                / bins.len() as f32)
+                .sqrt();
+
+            let peak = &mut self.peaks[index];
+            *peak = energy.max(*peak * peak_decay);
+            let target = if *peak > SILENCE { energy / *peak } else { 0.0 };
+
+            let level = &mut self.levels.bands[index];
+            let time = if target > *level {
+                self.settings.attack
+            } else {
+                self.settings.release
+            };
+            *level += (target - *level) * smoothing(timestep, time);
+        }
+
+        // Onsets: the spectral flux, or how much the spectrum grew since the
+        // last frame, against its recent average.
+        let flux = spectrum
+            .iter()
+            .zip(&self.previous_spectrum)
+            .map(|(current, previous)| (current - previous).max(0.0))
+            .sum::<f32>();
+        self.previous_spectrum = spectrum;
+
+        self.time_since_onset += timestep;
+        self.levels.onset *= 1.0 - smoothing(timestep, self.settings.onset_decay);
+        if flux > MIN_FLUX
+            && flux > self.settings.onset_threshold * self.flux_average
+            && self.time_since_onset >= MIN_ONSET_INTERVAL
+        {
+            self.levels.onset = 1.0;
+            self.time_since_onset = 0.0;
+        }
+        self.flux_average += (flux - self.flux_average) * smoothing(timestep, FLUX_AVERAGE_TIME);
+    }
+
+    // The spectrum bins that cover a band. Narrow bands get the nearest bin.
+    fn bins(&self, [low, high]: [f32; 2]) -> std::ops::Range<usize> {
+        let bin_width = self.sample_rate as f32 / FRAME_SIZE as f32;
+        let last_bin = FRAME_SIZE / 2;
+
+        let first = ((low / bin_width).ceil() as usize).min(last_bin);
+        let last = ((high / bin_width).floor() as usize).min(last_bin);
+        if first <= last {
+            first..last + 1
+        } else {
+            let nearest = ((0.5 * (low + high) / bin_width).round() as usize).min(last_bin);
+            nearest..nearest + 1
+        }
+    }
+}
+
+// How far to move towards a target in `timestep` seconds, when it takes about
+// `time` seconds to get there.
+fn smoothing(timestep: f32, time: f32) -> f32 {
+    if time > 0.0 {
+        1.0 - (-timestep / time).exp()
+    } else {
+        1.0
+    }
+}
+
+// How much the audio scales each setting. 1 leaves the setting as it is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Modulation {
+    pub noise_multiplier: f32,
+    pub noise_channel_multipliers: Vec<f32>,
+    pub line_width: f32,
+    pub color_transition_speed: f32,
+}
+
+impl Default for Modulation {
+    fn default() -> Self {
+        Self {
+            noise_multiplier: 1.0,
+            noise_channel_multipliers: Vec::new(),
+            line_width: 1.0,
+            color_transition_speed: 1.0,
+        }
+    }
+}
+
+impl Modulation {
+    pub fn new(levels: &Levels, settings: &settings::Settings) -> Self {
+        let mut modulation = Self {
+            noise_channel_multipliers: vec![1.0; settings.noise_channels.len()],
+            ..Default::default()
+        };
+
+        for mapping in &settings.audio.mappings {
+            let factor = (1.0 + mapping.amount * levels.get(mapping.source)).max(0.0);
+            match mapping.target {
+                AudioTarget::NoiseMultiplier => modulation.noise_multiplier *= factor,
+                AudioTarget::NoiseChannelMultiplier(index) => {
+                    if let Some(multiplier) = modulation.noise_channel_multipliers.get_mut(index) {
+                        *multiplier *= factor;
+                    }
+                }
+                AudioTarget::LineWidth => modulation.line_width *= factor,
+                AudioTarget::ColorTransitionSpeed => modulation.color_transition_speed *= factor,
+            }
+        }
+
+        modulation
+    }
+
+    pub fn get_noise_channel_multiplier(&self, index: usize) -> f32 {
+        self.noise_channel_multipliers
+            .get(index)
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+// An in-place, radix-2 FFT. The length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    // Reorder the samples by their bit-reversed indices.
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -TAU / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let (tr, ti) = (re[b] * cos - im[b] * sin, re[b] * sin + im[b] * cos);
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    fn sine(frequency: f32, seconds: f32) -> Vec<f32> {
+        let count = (seconds * SAMPLE_RATE as f32) as usize;
+        (0..count)
+            .map(|i| (TAU * frequency * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_16_bit_stereo() {
+        let samples: [i16; 4] = [0, 16384, -32768, 32767];
+        let data = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        for field in [1u16, 2] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [SAMPLE_RATE, 4 * SAMPLE_RATE] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [4u16, 16] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&data);
+
+        let wav = Wav::decode(&bytes).unwrap();
+        assert_eq!(wav.sample_rate, SAMPLE_RATE);
+        assert_eq!(wav.channels, 2);
+        assert_eq!(wav.samples[..3], [0.0, 0.5, -1.0]);
+        assert_eq!(wav.to_mono().len(), 2);
+
+        assert!(matches!(Wav::decode(b"RIFX"), Err(Problem::NotWav)));
+    }
+
+    #[test]
+    fn follows_the_bands() {
+        let mut analyzer = Analyzer::new(SAMPLE_RATE, &settings::Audio::default());
+        analyzer.feed(&sine(100.0, 0.5));
+
+        let levels = analyzer.get_levels();
+        assert!(levels.bands[0] > 0.9, "{:?}", levels);
+        assert!(levels.bands[2] < 0.1, "{:?}", levels);
+    }
+
+    #[test]
+    fn resumes_from_a_snapshot() {
+        let settings = settings::Audio::default();
+        let mut analyzer = Analyzer::new(SAMPLE_RATE, &settings);
+        analyzer.feed(&sine(100.0, 0.3));
+        let mut restored = Analyzer::restore(&analyzer.snapshot(), &settings).unwrap();
+
+        let samples = sine(2000.0, 0.3);
+        analyzer.feed(&samples);
+        restored.feed(&samples);
+        assert_eq!(restored.get_levels(), analyzer.get_levels());
+    }
+
+    #[test]
+    fn detects_onsets() {
+        let mut analyzer = Analyzer::new(SAMPLE_RATE, &settings::Audio::default());
+        analyzer.feed(&vec![0.0; SAMPLE_RATE as usize / 2]);
+        assert_eq!(analyzer.get_levels().onset, 0.0);
+
+        analyzer.feed(&sine(440.0, 0.05));
+        assert!(analyzer.get_levels().onset > 0.5);
+
+        // A steady tone isn’t an onset, so the onset fades out.
+        analyzer.feed(&sine(440.0, 1.0));
+        assert!(analyzer.get_levels().onset < 0.1);
+    }
+}
@@ -0,0 +1,141 @@
+// A CPU stand-in for a 2D float texture.
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field<T> {
+    width: u32,
+    height: u32,
+    // Row by row, starting with y = 0.
+    data: Vec<T>,
+}
+
+// The values a field can hold: f32 for R32Float, [f32; 2] for Rg32Float.
+pub trait Texel: Copy + Default {
+    fn mix(self, other: Self, t: f32) -> Self;
+}
+
+impl Texel for f32 {
+    fn mix(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Texel for [f32; 2] {
+    fn mix(self, other: Self, t: f32) -> Self {
+        [self[0].mix(other[0], t), self[1].mix(other[1], t)]
+    }
+}
+
+impl<T: Texel> Field<T> {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::filled(width, height, T::default())
+    }
+
+    pub fn filled(width: u32, height: u32, value: T) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![value; (width * height) as usize],
+        }
+    }
+
+    pub fn from_fn(width: u32, height: u32, mut f: impl FnMut(u32, u32) -> T) -> Self {
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> T {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, value: T) {
+        self.data[(y * self.width + x) as usize] = value;
+    }
+
+    pub fn fill(&mut self, value: T) {
+        self.data.fill(value);
+    }
+
+    // Read a texel, clamping to the edge like `textureSampleLevel` with a
+    // nearest, ClampToEdge sampler.
+    pub fn load(&self, x: i64, y: i64) -> T {
+        let x = x.clamp(0, i64::from(self.width) - 1) as u32;
+        let y = y.clamp(0, i64::from(self.height) - 1) as u32;
+        self.get(x, y)
+    }
+
+    // Sample with a nearest, ClampToEdge sampler. The position is in texels,
+    // that is, normalized texture coordinates times the size.
+    pub fn sample_nearest(&self, x: f32, y: f32) -> T {
+        self.load(x.floor() as i64, y.floor() as i64)
+    }
+
+    // Read a texel, wrapping around the edges like a Repeat sampler.
+    pub fn load_wrapped(&self, x: i64, y: i64) -> T {
+        let x = x.rem_euclid(i64::from(self.width)) as u32;
+        let y = y.rem_euclid(i64::from(self.height)) as u32;
+        self.get(x, y)
+    }
+
+    // Sample with a linear, ClampToEdge sampler. The position is in texels,
+    // with texel centers at half-integers.
+    pub fn sample_linear(&self, x: f32, y: f32) -> T {
+        self.interpolate(x, y, Self::load)
+    }
+
+    // Sample with a linear, Repeat sampler.
+    pub fn sample_linear_wrapped(&self, x: f32, y: f32) -> T {
+        self.interpolate(x, y, Self::load_wrapped)
+    }
+
+    fn interpolate(&self, x: f32, y: f32, load: impl Fn(&Self, i64, i64) -> T) -> T {
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let bottom = load(self, x0, y0).mix(load(self, x0 + 1, y0), tx);
+        let top = load(self, x0, y0 + 1).mix(load(self, x0 + 1, y0 + 1), tx);
+        bottom.mix(top, ty)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn samples_like_a_clamped_texture() {
+        let field = Field::from_fn(2, 2, |x, y| (x + 2 * y) as f32);
+
+        // Texel centers
+        assert_eq!(field.sample_linear(0.5, 0.5), 0.0);
+        assert_eq!(field.sample_linear(1.5, 1.5), 3.0);
+        // Between all four texels
+        assert_eq!(field.sample_linear(1.0, 1.0), 1.5);
+        // Clamped to the edges
+        assert_eq!(field.sample_linear(-4.0, 0.5), 0.0);
+        assert_eq!(field.sample_nearest(1.9, 7.0), 3.0);
+        // Or wrapped around them
+        assert_eq!(field.sample_linear_wrapped(0.0, 0.5), 0.5);
+        assert_eq!(field.load_wrapped(-1, 3), 3.0);
+    }
+}
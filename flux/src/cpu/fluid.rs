@@ -0,0 +1,760 @@
+// The fluid passes, one function per shader.
+//
+// Each pass reads its inputs and returns a new field, like the compute shaders
+// that write into a second texture. Neighbours are read the way the shaders
+// read them, including the half-texel offsets of the linear samples.
+
+use super::field::{Field, Texel};
+use crate::render::pressure;
+use crate::settings::{Boundary, PressureMode, PressureSolver, Settings};
+
+// The CPU side of FluidUniforms, plus the obstacle mask.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameters {
+    pub timestep: f32,
+    pub dissipation: f32,
+    pub alpha: f32,
+    pub r_beta: f32,
+    pub center_factor: f32,
+    pub stencil_factor: f32,
+    pub boundary: Boundary,
+    pub vorticity_strength: f32,
+    // 1.0 inside obstacles, and 0.0 elsewhere. Matches the size of the fluid.
+    pub obstacle: Option<Field<f32>>,
+}
+
+impl Parameters {
+    pub fn new(settings: &Settings) -> Self {
+        // dx^2 / (rho * dt)
+        let center_factor = 1.0 / (settings.viscosity * settings.fluid_timestep);
+        let stencil_factor = 1.0 / (4.0 + center_factor);
+
+        Self {
+            timestep: settings.fluid_timestep,
+            dissipation: settings.velocity_dissipation,
+            alpha: -1.0,
+            r_beta: 0.25,
+            center_factor,
+            stencil_factor,
+            boundary: settings.boundary,
+            vorticity_strength: settings.vorticity_strength,
+            obstacle: None,
+        }
+    }
+}
+
+pub struct Fluid {
+    pub velocity: Field<[f32; 2]>,
+    pub advection_forward: Field<[f32; 2]>,
+    pub advection_reverse: Field<[f32; 2]>,
+    pub divergence: Field<f32>,
+    pub vorticity: Field<f32>,
+    pub pressure: Field<f32>,
+}
+
+impl Fluid {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            velocity: Field::new(width, height),
+            advection_forward: Field::new(width, height),
+            advection_reverse: Field::new(width, height),
+            divergence: Field::new(width, height),
+            vorticity: Field::new(width, height),
+            pressure: Field::new(width, height),
+        }
+    }
+
+    // Run one fluid timestep, injecting `noise` along the way.
+    pub fn step(&mut self, settings: &Settings, noise: &Field<[f32; 2]>) {
+        let parameters = Parameters::new(settings);
+
+        self.advection_forward = advect(&self.velocity, 1.0, &parameters);
+        self.advection_reverse = advect(&self.velocity, -1.0, &parameters);
+        self.velocity = adjust_advection(
+            &self.velocity,
+            &self.advection_forward,
+            &self.advection_reverse,
+            &parameters,
+        );
+
+        for _ in 0..settings.diffusion_iterations {
+            self.velocity = diffuse(&self.velocity, &parameters);
+        }
+
+        self.velocity = inject_noise(&self.velocity, noise, parameters.timestep);
+
+        if parameters.vorticity_strength > 0.0 {
+            self.vorticity = vorticity(&self.velocity, &parameters);
+            self.velocity = confine_vorticity(&self.velocity, &self.vorticity, &parameters);
+        }
+
+        self.divergence = divergence(&self.velocity, &parameters);
+
+        if let PressureMode::ClearWith(pressure) = settings.pressure_mode {
+            self.pressure.fill(pressure);
+        }
+        self.pressure = solve(&self.pressure, &self.divergence, &parameters, settings);
+
+        self.velocity = subtract_gradient(&self.velocity, &self.pressure, &parameters);
+    }
+}
+
+// advect.comp.wgsl
+pub fn advect(
+    velocity: &Field<[f32; 2]>,
+    direction: f32,
+    parameters: &Parameters,
+) -> Field<[f32; 2]> {
+    let decay = 1.0 + parameters.dissipation * parameters.timestep;
+
+    Field::from_fn(velocity.width(), velocity.height(), |x, y| {
+        let [vx, vy] = velocity.get(x, y);
+        let position_x = (x as f32 + 0.5) + direction * parameters.timestep * vx;
+        let position_y = (y as f32 + 0.5) + direction * parameters.timestep * vy;
+        let [new_vx, new_vy] = sample(velocity, position_x, position_y, parameters.boundary);
+        [new_vx / decay, new_vy / decay]
+    })
+}
+
+// adjust_advection.comp.wgsl
+pub fn adjust_advection(
+    velocity: &Field<[f32; 2]>,
+    forward: &Field<[f32; 2]>,
+    reverse: &Field<[f32; 2]>,
+    parameters: &Parameters,
+) -> Field<[f32; 2]> {
+    Field::from_fn(velocity.width(), velocity.height(), |x, y| {
+        let [vx, vy] = velocity.get(x, y);
+        // The texel that the velocity was advected from, with the shader’s
+        // one-texel shift.
+        let from_x = ((x as f32 + 1.0) - parameters.timestep * vx).floor() as i64;
+        let from_y = ((y as f32 + 1.0) - parameters.timestep * vy).floor() as i64;
+        let neighbours =
+            OFFSETS.map(|(dx, dy)| load(velocity, from_x + dx, from_y + dy, parameters.boundary));
+
+        let [fx, fy] = forward.get(x, y);
+        let [rx, ry] = reverse.get(x, y);
+        let adjusted = [fx + 0.5 * (vx - rx), fy + 0.5 * (vy - ry)];
+
+        [0, 1].map(|axis| {
+            let min = neighbours.iter().map(|v| v[axis]).fold(f32::MAX, f32::min);
+            let max = neighbours.iter().map(|v| v[axis]).fold(f32::MIN, f32::max);
+            adjusted[axis].clamp(min, max)
+        })
+    })
+}
+
+// diffuse.comp.wgsl: one Jacobi iteration of the viscous diffusion.
+pub fn diffuse(velocity: &Field<[f32; 2]>, parameters: &Parameters) -> Field<[f32; 2]> {
+    Field::from_fn(velocity.width(), velocity.height(), |x, y| {
+        let (x, y) = (i64::from(x), i64::from(y));
+        if is_solid(velocity, x, y, parameters) {
+            return [0.0; 2];
+        }
+
+        let [l, r, b, t] = velocity_neighbours(velocity, x, y, parameters);
+        let center = velocity.load(x, y);
+        [0, 1].map(|axis| {
+            parameters.stencil_factor
+                * (l[axis] + r[axis] + b[axis] + t[axis] + parameters.center_factor * center[axis])
+        })
+    })
+}
+
+// inject_noise.comp.wgsl
+pub fn inject_noise(
+    velocity: &Field<[f32; 2]>,
+    noise: &Field<[f32; 2]>,
+    timestep: f32,
+) -> Field<[f32; 2]> {
+    let scale_x = noise.width() as f32 / velocity.width() as f32;
+    let scale_y = noise.height() as f32 / velocity.height() as f32;
+
+    Field::from_fn(velocity.width(), velocity.height(), |x, y| {
+        let [vx, vy] = velocity.get(x, y);
+        let [nx, ny] = noise.sample_linear(scale_x * x as f32, scale_y * y as f32);
+        [vx + timestep * nx, vy + timestep * ny]
+    })
+}
+
+// divergence.comp.wgsl
+pub fn divergence(velocity: &Field<[f32; 2]>, parameters: &Parameters) -> Field<f32> {
+    Field::from_fn(velocity.width(), velocity.height(), |x, y| {
+        let (x, y) = (i64::from(x), i64::from(y));
+        if is_solid(velocity, x, y, parameters) {
+            return 0.0;
+        }
+
+        let [l, r, b, t] = velocity_neighbours(velocity, x, y, parameters);
+        0.5 * ((r[0] - l[0]) + (t[1] - b[1]))
+    })
+}
+
+// vorticity.comp.wgsl: the curl of the velocity, positive counter-clockwise.
+pub fn vorticity(velocity: &Field<[f32; 2]>, parameters: &Parameters) -> Field<f32> {
+    Field::from_fn(velocity.width(), velocity.height(), |x, y| {
+        let (x, y) = (i64::from(x), i64::from(y));
+        if is_solid(velocity, x, y, parameters) {
+            return 0.0;
+        }
+
+        let [l, r, b, t] = velocity_neighbours(velocity, x, y, parameters);
+        0.5 * ((r[1] - l[1]) - (t[0] - b[0]))
+    })
+}
+
+// confine_vorticity.comp.wgsl
+pub fn confine_vorticity(
+    velocity: &Field<[f32; 2]>,
+    vorticity: &Field<f32>,
+    parameters: &Parameters,
+) -> Field<[f32; 2]> {
+    Field::from_fn(velocity.width(), velocity.height(), |x, y| {
+        let (x, y) = (i64::from(x), i64::from(y));
+        let [l, r, b, t] =
+            OFFSETS.map(|(dx, dy)| load(vorticity, x + dx, y + dy, parameters.boundary).abs());
+
+        let [gx, gy] = [0.5 * (r - l), 0.5 * (t - b)];
+        let length = (gx * gx + gy * gy).sqrt() + 1e-5;
+        let [nx, ny] = [gx / length, gy / length];
+
+        let strength = parameters.vorticity_strength * vorticity.load(x, y);
+        let [vx, vy] = velocity.load(x, y);
+        [
+            vx + parameters.timestep * strength * ny,
+            vy - parameters.timestep * strength * nx,
+        ]
+    })
+}
+
+// Solve for the pressure with the solver from the settings, stopping early
+// where the GPU would.
+pub fn solve(
+    pressure: &Field<f32>,
+    divergence: &Field<f32>,
+    parameters: &Parameters,
+    settings: &Settings,
+) -> Field<f32> {
+    let iterations = settings.pressure_iterations;
+    let checks = pressure::get_residual_checks(settings.pressure_solver, iterations);
+
+    let mut pressure = pressure.clone();
+    for iteration in 0..iterations {
+        if let Some(tolerance) = settings.pressure_tolerance {
+            if checks.contains(&iteration)
+                && max_residual(&pressure, divergence, parameters) < tolerance
+            {
+                break;
+            }
+        }
+
+        pressure = match settings.pressure_solver {
+            PressureSolver::Jacobi => solve_pressure(&pressure, divergence, parameters),
+            PressureSolver::RedBlackGaussSeidel => {
+                red_black_sweep(&pressure, divergence, parameters)
+            }
+            PressureSolver::Multigrid => v_cycle(&pressure, divergence, parameters),
+        };
+    }
+    pressure
+}
+
+// solve_pressure.comp.wgsl: one Jacobi iteration of the pressure solve, with
+// a pure Neumann condition at the edges.
+pub fn solve_pressure(
+    pressure: &Field<f32>,
+    divergence: &Field<f32>,
+    parameters: &Parameters,
+) -> Field<f32> {
+    Field::from_fn(pressure.width(), pressure.height(), |x, y| {
+        relax(pressure, divergence, parameters, x, y)
+    })
+}
+
+// solve_pressure_red_black.comp.wgsl: update the cells where x + y has the
+// given parity, and copy the rest.
+pub fn solve_pressure_red_black(
+    pressure: &Field<f32>,
+    divergence: &Field<f32>,
+    parameters: &Parameters,
+    parity: u32,
+) -> Field<f32> {
+    Field::from_fn(pressure.width(), pressure.height(), |x, y| {
+        if (x + y) % 2 == parity {
+            relax(pressure, divergence, parameters, x, y)
+        } else {
+            pressure.get(x, y)
+        }
+    })
+}
+
+// residual.comp.wgsl: how far each cell is from solving the pressure equation,
+// in units of divergence.
+pub fn residual(
+    pressure: &Field<f32>,
+    divergence: &Field<f32>,
+    parameters: &Parameters,
+) -> Field<f32> {
+    Field::from_fn(pressure.width(), pressure.height(), |x, y| {
+        // The pressure inside obstacles doesn’t need to converge.
+        if is_solid(pressure, i64::from(x), i64::from(y), parameters) {
+            return 0.0;
+        }
+
+        let [l, r, b, t] = pressure_neighbours(pressure, x, y, parameters);
+        divergence.get(x, y)
+            + (l + r + b + t - pressure.get(x, y) / parameters.r_beta) / parameters.alpha
+    })
+}
+
+// restrict.comp.wgsl: carry the residual down to a grid of half the size.
+//
+// The coarse grid spacing is twice as large, which scales the Laplacian by a
+// quarter. Summing the four fine cells, instead of averaging them, makes up
+// for that, so the coarse grid can use the same stencil.
+pub fn restrict(residual: &Field<f32>) -> Field<f32> {
+    Field::from_fn(residual.width() / 2, residual.height() / 2, |x, y| {
+        let (x, y) = (2 * x, 2 * y);
+        residual.get(x, y)
+            + residual.get(x + 1, y)
+            + residual.get(x, y + 1)
+            + residual.get(x + 1, y + 1)
+    })
+}
+
+// prolongate.comp.wgsl: add the coarse-grid correction back onto the pressure.
+pub fn prolongate(
+    pressure: &Field<f32>,
+    correction: &Field<f32>,
+    parameters: &Parameters,
+) -> Field<f32> {
+    Field::from_fn(pressure.width(), pressure.height(), |x, y| {
+        let position_x = 0.5 * (x as f32 + 0.5);
+        let position_y = 0.5 * (y as f32 + 0.5);
+        pressure.get(x, y) + sample(correction, position_x, position_y, parameters.boundary)
+    })
+}
+
+// measure_residual.comp.wgsl
+pub fn max_residual(
+    pressure: &Field<f32>,
+    divergence: &Field<f32>,
+    parameters: &Parameters,
+) -> f32 {
+    residual(pressure, divergence, parameters)
+        .data()
+        .iter()
+        .fold(0.0, |max, value| value.abs().max(max))
+}
+
+// One red-black Gauss–Seidel iteration
+fn red_black_sweep(
+    pressure: &Field<f32>,
+    divergence: &Field<f32>,
+    parameters: &Parameters,
+) -> Field<f32> {
+    let pressure = solve_pressure_red_black(pressure, divergence, parameters, 0);
+    solve_pressure_red_black(&pressure, divergence, parameters, 1)
+}
+
+// One multigrid V-cycle, with as many levels as the GPU uses.
+pub fn v_cycle(
+    pressure: &Field<f32>,
+    divergence: &Field<f32>,
+    parameters: &Parameters,
+) -> Field<f32> {
+    let levels = pressure::get_level_sizes(pressure.width(), pressure.height()).len();
+    v_cycle_with_levels(pressure, divergence, parameters, levels)
+}
+
+fn v_cycle_with_levels(
+    pressure: &Field<f32>,
+    divergence: &Field<f32>,
+    parameters: &Parameters,
+    levels: usize,
+) -> Field<f32> {
+    let sweeps = |mut pressure: Field<f32>, count| {
+        for _ in 0..count {
+            pressure = red_black_sweep(&pressure, divergence, parameters);
+        }
+        pressure
+    };
+
+    if levels == 1 {
+        return sweeps(pressure.clone(), pressure::COARSEST_SWEEPS);
+    }
+
+    let pressure = sweeps(pressure.clone(), pressure::PRE_SWEEPS);
+    let coarse_divergence = restrict(&residual(&pressure, divergence, parameters));
+    let correction = v_cycle_with_levels(
+        &Field::new(coarse_divergence.width(), coarse_divergence.height()),
+        &coarse_divergence,
+        parameters,
+        levels - 1,
+    );
+    let pressure = prolongate(&pressure, &correction, parameters);
+    sweeps(pressure, pressure::POST_SWEEPS)
+}
+
+// The Jacobi update of a single cell
+fn relax(
+    pressure: &Field<f32>,
+    divergence: &Field<f32>,
+    parameters: &Parameters,
+    x: u32,
+    y: u32,
+) -> f32 {
+    let [l, r, b, t] = pressure_neighbours(pressure, x, y, parameters);
+    parameters.r_beta * (l + r + b + t + parameters.alpha * divergence.get(x, y))
+}
+
+// The neighbours of a pressure. Outside the grid, walls mirror the cell itself
+// for a pure Neumann condition, and open edges hold the pressure at zero.
+//
+// Obstacles mirror the cell too, but cells inside them read their neighbours
+// as usual.
+fn pressure_neighbours(pressure: &Field<f32>, x: u32, y: u32, parameters: &Parameters) -> [f32; 4] {
+    let (x, y) = (i64::from(x), i64::from(y));
+    let boundary = parameters.boundary;
+    let is_fluid = !is_solid(pressure, x, y, parameters);
+    OFFSETS.map(|(dx, dy)| {
+        if boundary != Boundary::Periodic && !is_inside(pressure, x + dx, y + dy) {
+            if boundary == Boundary::Open {
+                0.0
+            } else {
+                pressure.load(x, y)
+            }
+        } else if is_fluid && is_solid_neighbour(pressure, x + dx, y + dy, parameters) {
+            pressure.load(x, y)
+        } else {
+            load(pressure, x + dx, y + dy, boundary)
+        }
+    })
+}
+
+// subtract_gradient.comp.wgsl
+pub fn subtract_gradient(
+    velocity: &Field<[f32; 2]>,
+    pressure: &Field<f32>,
+    parameters: &Parameters,
+) -> Field<[f32; 2]> {
+    let (width, height) = (velocity.width(), velocity.height());
+    let boundary = parameters.boundary;
+
+    Field::from_fn(width, height, |x, y| {
+        // The shader samples the pressure with a linear sampler at the texel
+        // corner, so every read averages four texels.
+        let (px, py) = (x as f32, y as f32);
+        let mut l = sample(pressure, px - 1.0, py, boundary);
+        let mut r = sample(pressure, px + 1.0, py, boundary);
+        let mut b = sample(pressure, px, py - 1.0, boundary);
+        let mut t = sample(pressure, px, py + 1.0, boundary);
+
+        let is_edge_x = x == 0 || x == width - 1;
+        let is_edge_y = y == 0 || y == height - 1;
+
+        if boundary == Boundary::Open {
+            if x == 0 {
+                l = 0.0;
+            }
+            if x == width - 1 {
+                r = 0.0;
+            }
+            if y == 0 {
+                b = 0.0;
+            }
+            if y == height - 1 {
+                t = 0.0;
+            }
+        }
+
+        let [mut keep_x, mut keep_y] = match boundary {
+            Boundary::Closed if is_edge_x || is_edge_y => [0.0, 0.0],
+            Boundary::FreeSlip => [
+                if is_edge_x { 0.0 } else { 1.0 },
+                if is_edge_y { 0.0 } else { 1.0 },
+            ],
+            _ => [1.0, 1.0],
+        };
+
+        // Obstacles act like free-slip walls, and the fluid inside them stands
+        // still.
+        let (cx, cy) = (i64::from(x), i64::from(y));
+        let is_solid_at = |dx, dy| is_solid_neighbour(velocity, cx + dx, cy + dy, parameters);
+        if is_solid_at(-1, 0) || is_solid_at(1, 0) {
+            keep_x = 0.0;
+        }
+        if is_solid_at(0, -1) || is_solid_at(0, 1) {
+            keep_y = 0.0;
+        }
+        if is_solid(velocity, cx, cy, parameters) {
+            [keep_x, keep_y] = [0.0, 0.0];
+        }
+
+        let [vx, vy] = velocity.get(x, y);
+        [keep_x * (vx - 0.5 * (r - l)), keep_y * (vy - 0.5 * (t - b))]
+    })
+}
+
+// The left, right, bottom and top offsets
+const OFFSETS: [(i64, i64); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+// The neighbours of a velocity. Closed walls hold the velocity outside the
+// grid at zero, and obstacles hold it at zero inside them.
+fn velocity_neighbours(
+    velocity: &Field<[f32; 2]>,
+    x: i64,
+    y: i64,
+    parameters: &Parameters,
+) -> [[f32; 2]; 4] {
+    let boundary = parameters.boundary;
+    OFFSETS.map(|(dx, dy)| {
+        let (nx, ny) = match boundary {
+            Boundary::Closed if !is_inside(velocity, x + dx, y + dy) => return [0.0; 2],
+            Boundary::Periodic => wrap(velocity, x + dx, y + dy),
+            _ => clamp(velocity, x + dx, y + dy),
+        };
+        if is_solid(velocity, nx, ny, parameters) {
+            [0.0; 2]
+        } else {
+            velocity.load(nx, ny)
+        }
+    })
+}
+
+// Whether a cell of a grid lies inside an obstacle. Coarser grids than the
+// obstacle mask look it up at their cell centers.
+fn is_solid<T: Texel>(field: &Field<T>, x: i64, y: i64, parameters: &Parameters) -> bool {
+    parameters.obstacle.as_ref().is_some_and(|mask| {
+        let mask_x = (2 * x + 1) * i64::from(mask.width()) / (2 * i64::from(field.width()));
+        let mask_y = (2 * y + 1) * i64::from(mask.height()) / (2 * i64::from(field.height()));
+        mask.load(mask_x, mask_y) > 0.5
+    })
+}
+
+// Whether a neighbouring cell lies inside an obstacle. Outside the grid, the
+// boundary takes over.
+fn is_solid_neighbour<T: Texel>(field: &Field<T>, x: i64, y: i64, parameters: &Parameters) -> bool {
+    match parameters.boundary {
+        Boundary::Periodic => {
+            let (x, y) = wrap(field, x, y);
+            is_solid(field, x, y, parameters)
+        }
+        _ => is_inside(field, x, y) && is_solid(field, x, y, parameters),
+    }
+}
+
+fn wrap<T: Texel>(field: &Field<T>, x: i64, y: i64) -> (i64, i64) {
+    (
+        x.rem_euclid(i64::from(field.width())),
+        y.rem_euclid(i64::from(field.height())),
+    )
+}
+
+fn clamp<T: Texel>(field: &Field<T>, x: i64, y: i64) -> (i64, i64) {
+    (
+        x.clamp(0, i64::from(field.width()) - 1),
+        y.clamp(0, i64::from(field.height()) - 1),
+    )
+}
+
+fn is_inside<T: Texel>(field: &Field<T>, x: i64, y: i64) -> bool {
+    (0..i64::from(field.width())).contains(&x) && (0..i64::from(field.height())).contains(&y)
+}
+
+// Read a texel like the shaders do: clamped to the edges, or wrapped around
+// them for periodic boundaries.
+fn load<T: Texel>(field: &Field<T>, x: i64, y: i64, boundary: Boundary) -> T {
+    match boundary {
+        Boundary::Periodic => field.load_wrapped(x, y),
+        _ => field.load(x, y),
+    }
+}
+
+// Sample like the shaders’ linear sampler, which repeats for periodic
+// boundaries.
+fn sample<T: Texel>(field: &Field<T>, x: f32, y: f32, boundary: Boundary) -> T {
+    match boundary {
+        Boundary::Periodic => field.sample_linear_wrapped(x, y),
+        _ => field.sample_linear(x, y),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn projects_out_divergence() {
+        // A source in the middle of the grid.
+        let velocity = Field::from_fn(32, 32, |x, y| {
+            let (dx, dy) = (x as f32 - 15.5, y as f32 - 15.5);
+            let falloff = (-(dx * dx + dy * dy) / 32.0).exp();
+            [dx * falloff, dy * falloff]
+        });
+        let settings = Settings {
+            pressure_iterations: 200,
+            ..Default::default()
+        };
+        let parameters = Parameters::new(&settings);
+
+        let total_divergence = |velocity: &Field<[f32; 2]>| -> f32 {
+            divergence(velocity, &parameters)
+                .data()
+                .iter()
+                .map(|d| d.abs())
+                .sum()
+        };
+
+        let mut pressure = Field::new(32, 32);
+        let initial_divergence = divergence(&velocity, &parameters);
+        for _ in 0..settings.pressure_iterations {
+            pressure = solve_pressure(&pressure, &initial_divergence, &parameters);
+        }
+        let projected = subtract_gradient(&velocity, &pressure, &parameters);
+
+        assert!(total_divergence(&projected) < 0.5 * total_divergence(&velocity));
+    }
+
+    // A source in the middle of a 64x64 grid
+    fn source_divergence() -> Field<f32> {
+        let velocity = Field::from_fn(64, 64, |x, y| {
+            let (dx, dy) = (x as f32 - 31.5, y as f32 - 31.5);
+            let falloff = (-(dx * dx + dy * dy) / 128.0).exp();
+            [dx * falloff, dy * falloff]
+        });
+        divergence(&velocity, &Parameters::new(&Settings::default()))
+    }
+
+    #[test]
+    fn faster_solvers_leave_less_residual() {
+        let divergence = source_divergence();
+        let total_residual_after = |pressure_solver, pressure_iterations| -> f32 {
+            let settings = Settings {
+                pressure_solver,
+                pressure_iterations,
+                ..Default::default()
+            };
+            let parameters = Parameters::new(&settings);
+            let pressure = solve(&Field::new(64, 64), &divergence, &parameters, &settings);
+            residual(&pressure, &divergence, &parameters)
+                .data()
+                .iter()
+                .map(|r| r.abs())
+                .sum()
+        };
+
+        let jacobi = total_residual_after(PressureSolver::Jacobi, 20);
+        let red_black = total_residual_after(PressureSolver::RedBlackGaussSeidel, 20);
+        let multigrid = total_residual_after(PressureSolver::Multigrid, 3);
+        assert!(red_black < jacobi);
+        assert!(multigrid < 0.01 * red_black);
+    }
+
+    #[test]
+    fn stops_once_the_residual_is_below_the_tolerance() {
+        let divergence = source_divergence();
+        let settings = Settings {
+            pressure_solver: PressureSolver::Multigrid,
+            pressure_iterations: 10,
+            pressure_tolerance: Some(0.01),
+            ..Default::default()
+        };
+        let parameters = Parameters::new(&settings);
+        let pressure = solve(&Field::new(64, 64), &divergence, &parameters, &settings);
+        assert!(max_residual(&pressure, &divergence, &parameters) < 0.01);
+
+        // It stopped early, with the result of a shorter solve.
+        let stopped_after = (1..10).find(|&pressure_iterations| {
+            let settings = Settings {
+                pressure_iterations,
+                pressure_tolerance: None,
+                ..settings.clone()
+            };
+            solve(&Field::new(64, 64), &divergence, &parameters, &settings) == pressure
+        });
+        assert!(stopped_after.is_some());
+    }
+
+    #[test]
+    fn applies_the_boundary_at_the_edges() {
+        let velocity = Field::filled(16, 16, [0.3, -0.2]);
+        let project = |boundary| {
+            let settings = Settings {
+                boundary,
+                ..Default::default()
+            };
+            let parameters = Parameters::new(&settings);
+            let divergence = divergence(&velocity, &parameters);
+            let pressure = solve(&Field::new(16, 16), &divergence, &parameters, &settings);
+            subtract_gradient(&velocity, &pressure, &parameters)
+        };
+
+        // A uniform flow wraps around, or leaves through the open edges.
+        assert_eq!(project(Boundary::Periodic), velocity);
+        assert_eq!(project(Boundary::Open), velocity);
+
+        // Walls stop the flow across them, and closed walls stop it along them
+        // too.
+        let free_slip = project(Boundary::FreeSlip);
+        assert_eq!(free_slip.get(0, 8)[0], 0.0);
+        assert_ne!(free_slip.get(0, 8)[1], 0.0);
+        assert_eq!(project(Boundary::Closed).get(0, 8), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn spins_up_eddies() {
+        // A vortex that spins counter-clockwise around the middle of the grid
+        let velocity = Field::from_fn(32, 32, |x, y| {
+            let (dx, dy) = (x as f32 - 15.5, y as f32 - 15.5);
+            let falloff = (-(dx * dx + dy * dy) / 32.0).exp();
+            [-dy * falloff, dx * falloff]
+        });
+        let parameters = Parameters {
+            vorticity_strength: 20.0,
+            ..Parameters::new(&Settings::default())
+        };
+
+        let initial_vorticity = vorticity(&velocity, &parameters);
+        assert!(initial_vorticity.get(16, 16) > 0.0);
+
+        // The confinement pushes the fluid along with the vortex, so it spins
+        // faster.
+        let confined = confine_vorticity(&velocity, &initial_vorticity, &parameters);
+        assert!(confined.get(20, 16)[1] > velocity.get(20, 16)[1]);
+        assert!(confined.get(11, 16)[1] < velocity.get(11, 16)[1]);
+        assert!(vorticity(&confined, &parameters).get(16, 16) > initial_vorticity.get(16, 16));
+    }
+
+    #[test]
+    fn flows_around_obstacles() {
+        // A uniform flow towards a block in the middle
+        let velocity = Field::filled(32, 16, [1.0, 0.0]);
+        let obstacle = Field::from_fn(32, 16, |x, y| {
+            if (12..20).contains(&x) && (4..12).contains(&y) {
+                1.0
+            } else {
+                0.0
+            }
+        });
+        let settings = Settings {
+            pressure_iterations: 100,
+            ..Default::default()
+        };
+        let parameters = Parameters {
+            obstacle: Some(obstacle),
+            ..Parameters::new(&settings)
+        };
+        let divergence = divergence(&velocity, &parameters);
+        let pressure = solve(&Field::new(32, 16), &divergence, &parameters, &settings);
+        let projected = subtract_gradient(&velocity, &pressure, &parameters);
+
+        // Nothing flows into the block, or inside it.
+        assert_eq!(projected.get(11, 8)[0], 0.0);
+        assert_eq!(projected.get(16, 8), [0.0, 0.0]);
+
+        // The flow splits to go around it.
+        assert!(projected.get(10, 10)[1] > 0.0);
+        assert!(projected.get(10, 5)[1] < 0.0);
+    }
+}
@@ -0,0 +1,290 @@
+// The line spring update: place_lines.comp.wgsl.
+//
+// Color transitions aren’t simulated: the lines always head for the current
+// colors.
+
+use super::field::Field;
+use super::noise::snoise;
+use crate::grid::Grid;
+use crate::render::lines::{get_line_scale_factor, tick_line_noise};
+use crate::settings::{ColorMode, Interpolation, Settings};
+
+use glam::{Vec2, Vec3, Vec4, Vec4Swizzles};
+use std::f32::consts::{PI, TAU};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Line {
+    pub endpoint: [f32; 2],
+    pub velocity: [f32; 2],
+    pub color: [f32; 4],
+    pub color_velocity: [f32; 3],
+    pub width: f32,
+}
+
+// How the lines pick their colors.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Colors {
+    // Color by the direction of the velocity
+    Original,
+    // A color wheel, from a palette’s color stops
+    Stops {
+        stops: Vec<[f32; 4]>,
+        interpolation: Interpolation,
+    },
+}
+
+impl Colors {
+    pub fn new(color_mode: &ColorMode) -> Self {
+        let palette = match color_mode {
+            ColorMode::Preset(preset) => preset.to_palette(),
+            ColorMode::Palette(palette) => Some(palette.clone()),
+            ColorMode::ImageFile(_) => {
+                log::warn!("🖼 The CPU simulation can’t sample colors from images. Using the original colors instead");
+                None
+            }
+        };
+
+        match palette {
+            Some(palette) => Self::Stops {
+                stops: palette.to_color_stops(),
+                interpolation: palette.interpolation,
+            },
+            None => Self::Original,
+        }
+    }
+}
+
+// The parts of `LineUniforms` that affect the spring update.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameters {
+    pub line_length: f32,
+    pub line_variance: f32,
+    pub line_noise_scale: [f32; 2],
+    pub line_noise_offset_1: f32,
+    pub colors: Colors,
+}
+
+impl Parameters {
+    pub fn new(logical_width: u32, logical_height: u32, grid: &Grid, settings: &Settings) -> Self {
+        let line_scale_factor = get_line_scale_factor(logical_width as f32, logical_height as f32);
+
+        Self {
+            line_length: settings.view_scale * settings.line_length * line_scale_factor,
+            line_variance: settings.line_variance,
+            line_noise_scale: [64.0 * grid.scaling_ratio.x(), 64.0 * grid.scaling_ratio.y()],
+            line_noise_offset_1: 0.0,
+            colors: Colors::new(&settings.color_mode),
+        }
+    }
+}
+
+pub struct Lines {
+    parameters: Parameters,
+    // [offset_1, offset_2, blend_factor]
+    line_noise: [f32; 3],
+    basepoints: Vec<[f32; 2]>,
+    lines: Vec<Line>,
+}
+
+impl Lines {
+    pub fn new(logical_width: u32, logical_height: u32, grid: &Grid, settings: &Settings) -> Self {
+        Self {
+            parameters: Parameters::new(logical_width, logical_height, grid, settings),
+            line_noise: [0.0; 3],
+            basepoints: grid
+                .basepoints
+                .chunks_exact(2)
+                .map(|point| [point[0], point[1]])
+                .collect(),
+            lines: vec![Line::default(); grid.line_count as usize],
+        }
+    }
+
+    pub fn step(&mut self, velocity: &Field<[f32; 2]>, timestep: f32, elapsed_time: f32) {
+        tick_line_noise(&mut self.line_noise, elapsed_time);
+        self.parameters.line_noise_offset_1 = self.line_noise[0];
+
+        self.lines = place_lines(
+            &self.lines,
+            &self.basepoints,
+            velocity,
+            &self.parameters,
+            timestep,
+        );
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    pub fn basepoints(&self) -> &[[f32; 2]] {
+        &self.basepoints
+    }
+}
+
+pub fn place_lines(
+    lines: &[Line],
+    basepoints: &[[f32; 2]],
+    velocity_field: &Field<[f32; 2]>,
+    parameters: &Parameters,
+    timestep: f32,
+) -> Vec<Line> {
+    let size = Vec2::new(
+        velocity_field.width() as f32,
+        velocity_field.height() as f32,
+    );
+
+    lines
+        .iter()
+        .zip(basepoints)
+        .map(|(line, basepoint)| {
+            let basepoint = Vec2::from(*basepoint);
+            let position = basepoint * size;
+            let velocity = Vec2::from(velocity_field.sample_linear(position.x, position.y));
+            let noise = snoise(
+                (Vec2::from(parameters.line_noise_scale) * basepoint)
+                    .extend(parameters.line_noise_offset_1),
+            );
+
+            let variance = mix(1.0 - parameters.line_variance, 1.0, 0.5 + 0.5 * noise);
+            let velocity_delta_boost = mix(3.0, 25.0, 1.0 - variance);
+            let momentum_boost = mix(3.0, 5.0, variance);
+
+            let endpoint = Vec2::from(line.endpoint);
+            let new_velocity = (1.0 - timestep * momentum_boost) * Vec2::from(line.velocity)
+                + (parameters.line_length * velocity - endpoint) * velocity_delta_boost * timestep;
+            let new_endpoint = endpoint + timestep * new_velocity;
+
+            let width_boost = (2.5 * velocity.length()).clamp(0.0, 1.0);
+            let new_width = width_boost * width_boost * (3.0 - width_boost * 2.0);
+
+            let (color, color_momentum_boost, color_delta_boost) =
+                get_line_color(&parameters.colors, velocity);
+            let rgb = Vec4::from(line.color).xyz();
+            let new_color_velocity = Vec3::from(line.color_velocity)
+                * (1.0 - color_momentum_boost * timestep)
+                + (color - rgb) * color_delta_boost * timestep;
+            let new_color = (rgb + timestep * new_color_velocity)
+                .clamp(Vec3::ZERO, Vec3::ONE)
+                .extend(width_boost);
+
+            Line {
+                endpoint: new_endpoint.to_array(),
+                velocity: new_velocity.to_array(),
+                color: new_color.to_array(),
+                color_velocity: new_color_velocity.to_array(),
+                width: new_width,
+            }
+        })
+        .collect()
+}
+
+// The target color, momentum boost and delta boost.
+fn get_line_color(colors: &Colors, velocity: Vec2) -> (Vec3, f32, f32) {
+    match colors {
+        Colors::Original => {
+            let color = (Vec2::new(1.0, 0.66) * (0.5 + velocity)).clamp(Vec2::ZERO, Vec2::ONE);
+            (color.extend(0.5), 3.0, 90.0)
+        }
+        Colors::Stops {
+            stops,
+            interpolation,
+        } => {
+            let angle = velocity.y.atan2(velocity.x);
+            let color = get_color(stops, *interpolation, angle + PI, TAU);
+            (color, 3.0, 90.0)
+        }
+    }
+}
+
+// Get a color from the ring of color stops. See `get_color` in the shader.
+fn get_color(stops: &[[f32; 4]], interpolation: Interpolation, value: f32, limit: f32) -> Vec3 {
+    let position = fract(value / limit);
+
+    let index = stops
+        .iter()
+        .rposition(|stop| stop[3] <= position)
+        .unwrap_or(stops.len() - 1);
+    let next_index = (index + 1) % stops.len();
+
+    let current_stop = Vec4::from(stops[index]);
+    let next_stop = Vec4::from(stops[next_index]);
+    let span = fract(next_stop.w - current_stop.w);
+    let t = if span > 0.0 {
+        (fract(position - current_stop.w) / span).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    mix_colors(interpolation, current_stop.xyz(), next_stop.xyz(), t)
+}
+
+fn mix_colors(interpolation: Interpolation, a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    match interpolation {
+        Interpolation::Srgb => a.lerp(b, t),
+        Interpolation::LinearRgb => linear_to_srgb(srgb_to_linear(a).lerp(srgb_to_linear(b), t)),
+        Interpolation::Oklab => {
+            let lab_a = linear_srgb_to_oklab(srgb_to_linear(a));
+            let lab_b = linear_srgb_to_oklab(srgb_to_linear(b));
+            linear_to_srgb(oklab_to_linear_srgb(lab_a.lerp(lab_b, t)).clamp(Vec3::ZERO, Vec3::ONE))
+        }
+    }
+}
+
+fn srgb_to_linear(color: Vec3) -> Vec3 {
+    color.map(|c| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    })
+}
+
+fn linear_to_srgb(color: Vec3) -> Vec3 {
+    color.map(|c| {
+        if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    })
+}
+
+// https://bottosson.github.io/posts/oklab/
+fn linear_srgb_to_oklab(c: Vec3) -> Vec3 {
+    let l = 0.41222147 * c.x + 0.53633254 * c.y + 0.05144599 * c.z;
+    let m = 0.2119035 * c.x + 0.6806995 * c.y + 0.10739696 * c.z;
+    let s = 0.08830246 * c.x + 0.28171884 * c.y + 0.6299787 * c.z;
+
+    let [l_, m_, s_] = [l, m, s].map(|v| v.max(0.0).cbrt());
+
+    Vec3::new(
+        0.21045426 * l_ + 0.7936178 * m_ - 0.004072047 * s_,
+        1.9779985 * l_ - 2.4285922 * m_ + 0.4505937 * s_,
+        0.025904037 * l_ + 0.78277177 * m_ - 0.80867577 * s_,
+    )
+}
+
+fn oklab_to_linear_srgb(c: Vec3) -> Vec3 {
+    let l_ = c.x + 0.39633778 * c.y + 0.21580376 * c.z;
+    let m_ = c.x - 0.105561346 * c.y - 0.06385417 * c.z;
+    let s_ = c.x - 0.08948418 * c.y - 1.2914855 * c.z;
+
+    let [l, m, s] = [l_, m_, s_].map(|v| v * v * v);
+
+    Vec3::new(
+        4.0767417 * l - 3.3077116 * m + 0.23096993 * s,
+        -1.268438 * l + 2.6097574 * m - 0.34131938 * s,
+        -0.0041960864 * l - 0.7034186 * m + 1.7076147 * s,
+    )
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// GLSL’s `fract`, which, unlike `f32::fract`, wraps negative values into [0, 1).
+fn fract(value: f32) -> f32 {
+    value - value.floor()
+}
@@ -0,0 +1,142 @@
+// A pure-CPU version of the simulation.
+//
+// Runs the same passes as the compute shaders on plain vectors: the fluid,
+// the noise that drives it, and the line spring update. It doesn’t render
+// anything.
+//
+// Use it to run the simulation where there’s no GPU, or as a reference when
+// checking the shaders. Each pass is a public function that mirrors its
+// shader, sampling included. Given the same seed and timesteps, the CPU and
+// GPU simulations agree up to floating-point differences.
+
+pub mod field;
+pub mod fluid;
+pub mod lines;
+pub mod noise;
+
+pub use field::Field;
+
+use crate::flux::MAX_ELAPSED_TIME;
+use crate::settings::{Settings, ValidationError};
+use crate::{grid, rng};
+
+use std::sync::Arc;
+
+pub struct Simulation {
+    settings: Arc<Settings>,
+    fluid: fluid::Fluid,
+    noise: noise::Noise,
+    lines: lines::Lines,
+
+    // A local animation timer in seconds that resets at MAX_ELAPSED_TIME.
+    elapsed_time: f32,
+    fluid_frame_time: f32,
+}
+
+impl Simulation {
+    pub fn new(
+        logical_width: u32,
+        logical_height: u32,
+        settings: &Arc<Settings>,
+    ) -> Result<Self, ValidationError> {
+        settings.validate()?;
+
+        rng::init_from_seed(&settings.seed);
+
+        let grid = grid::Grid::new(logical_width, logical_height, settings.grid_spacing);
+        let (width, height) = (
+            grid.scaling_ratio.rounded_x() * settings.fluid_size,
+            grid.scaling_ratio.rounded_y() * settings.fluid_size,
+        );
+
+        let fluid = fluid::Fluid::new(width, height);
+        let lines = lines::Lines::new(logical_width, logical_height, &grid, settings);
+        let noise = noise::Noise::new(2 * width, 2 * height, grid.scaling_ratio, settings);
+
+        Ok(Self {
+            settings: Arc::clone(settings),
+            fluid,
+            noise,
+            lines,
+            elapsed_time: 0.0,
+            fluid_frame_time: 0.0,
+        })
+    }
+
+    // Advance the simulation by `timestep` seconds, like `Flux::step`.
+    pub fn step(&mut self, timestep: f32) {
+        self.elapsed_time += timestep;
+        self.fluid_frame_time += timestep;
+
+        // Reset animation timers to avoid precision issues
+        let timer_overflow = self.elapsed_time - MAX_ELAPSED_TIME;
+        if timer_overflow >= 0.0 {
+            self.elapsed_time = timer_overflow;
+        }
+
+        while self.fluid_frame_time >= self.settings.fluid_timestep {
+            self.noise.tick(self.settings.fluid_timestep);
+            let noise = self.noise.generate();
+            self.fluid.step(&self.settings, noise);
+
+            self.fluid_frame_time -= self.settings.fluid_timestep;
+        }
+
+        self.lines
+            .step(&self.fluid.velocity, timestep, self.elapsed_time);
+    }
+
+    pub fn get_velocity(&self) -> &Field<[f32; 2]> {
+        &self.fluid.velocity
+    }
+
+    pub fn get_pressure(&self) -> &Field<f32> {
+        &self.fluid.pressure
+    }
+
+    pub fn get_divergence(&self) -> &Field<f32> {
+        &self.fluid.divergence
+    }
+
+    pub fn get_noise(&self) -> &Field<[f32; 2]> {
+        self.noise.field()
+    }
+
+    pub fn get_lines(&self) -> &[lines::Line] {
+        self.lines.lines()
+    }
+
+    pub fn get_basepoints(&self) -> &[[f32; 2]] {
+        self.lines.basepoints()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_lines() {
+        let settings = Arc::new(Settings {
+            seed: Some("cpu".to_string()),
+            fluid_size: 16,
+            grid_spacing: 40,
+            ..Default::default()
+        });
+
+        let run = || {
+            let mut simulation = Simulation::new(320, 200, &settings).unwrap();
+            for _ in 0..30 {
+                simulation.step(1.0 / 60.0);
+            }
+            simulation.get_lines().to_vec()
+        };
+
+        let lines = run();
+        assert_eq!(lines, run());
+        assert!(lines.iter().any(|line| line.endpoint != [0.0, 0.0]));
+        assert!(lines
+            .iter()
+            .all(|line| line.endpoint.iter().all(|v| v.is_finite())));
+    }
+}
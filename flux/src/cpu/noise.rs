@@ -0,0 +1,298 @@
+// The noise that drives the fluid: generate_noise.comp.wgsl and the channel
+// updates in `NoiseGenerator`.
+
+use super::field::Field;
+use crate::render::noise::NoiseChannel;
+use crate::{grid, settings};
+
+use glam::{Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+
+pub struct Noise {
+    elapsed_time: f32,
+    multiplier: f32,
+    channel_settings: Vec<settings::Noise>,
+    channels: Vec<NoiseChannel>,
+    field: Field<[f32; 2]>,
+}
+
+impl Noise {
+    // Draws the channel offsets from the shared RNG, in the same order as the
+    // GPU noise generator.
+    pub fn new(
+        width: u32,
+        height: u32,
+        scaling_ratio: grid::ScalingRatio,
+        settings: &settings::Settings,
+    ) -> Self {
+        let channels = settings
+            .noise_channels
+            .iter()
+            .map(|channel| NoiseChannel::new(scaling_ratio, channel))
+            .collect();
+
+        Self {
+            elapsed_time: 0.0,
+            multiplier: settings.noise_multiplier,
+            channel_settings: settings.noise_channels.clone(),
+            channels,
+            field: Field::new(width, height),
+        }
+    }
+
+    pub fn tick(&mut self, timestep: f32) {
+        self.elapsed_time += timestep;
+
+        for (channel, channel_settings) in self.channels.iter_mut().zip(&self.channel_settings) {
+            channel.tick(channel_settings, self.elapsed_time);
+        }
+    }
+
+    pub fn generate(&mut self) -> &Field<[f32; 2]> {
+        self.field = generate(
+            self.field.width(),
+            self.field.height(),
+            &self.channels,
+            self.multiplier,
+        );
+        &self.field
+    }
+
+    pub fn field(&self) -> &Field<[f32; 2]> {
+        &self.field
+    }
+}
+
+pub fn generate(
+    width: u32,
+    height: u32,
+    channels: &[NoiseChannel],
+    multiplier: f32,
+) -> Field<[f32; 2]> {
+    let size = Vec2::new(width as f32, height as f32);
+
+    Field::from_fn(width, height, |x, y| {
+        let texel_position = Vec2::new(x as f32, y as f32) / size;
+        let noise = channels
+            .iter()
+            .map(|channel| make_noise(texel_position, channel, size))
+            .sum::<Vec2>();
+        (multiplier * noise).to_array()
+    })
+}
+
+fn make_noise(texel_position: Vec2, channel: &NoiseChannel, size: Vec2) -> Vec2 {
+    let scale = Vec2::from(channel.scale) * texel_position;
+    let mut noise = make_noise_of_kind(scale, channel.offset_1, channel, size);
+
+    if channel.blend_factor > 0.0 {
+        let noise_2 = make_noise_of_kind(scale, channel.offset_2, channel, size);
+        noise = noise.lerp(noise_2, channel.blend_factor);
+    }
+
+    channel.multiplier * noise
+}
+
+// The kinds follow the constants in the shader.
+fn make_noise_of_kind(position: Vec2, offset: f32, channel: &NoiseChannel, size: Vec2) -> Vec2 {
+    match channel.kind {
+        1 => make_curl_noise(position, offset, channel, size),
+        2 => make_fbm_noise(position, offset, channel),
+        3 => make_domain_warped_noise(position, offset, channel),
+        _ => make_noise_pair(position.extend(offset)),
+    }
+}
+
+fn make_curl_noise(position: Vec2, offset: f32, channel: &NoiseChannel, size: Vec2) -> Vec2 {
+    const EPSILON: f32 = 0.01;
+    let params = position.extend(offset);
+    let (dx, dy) = (Vec3::new(EPSILON, 0.0, 0.0), Vec3::new(0.0, EPSILON, 0.0));
+    let gradient = Vec2::new(
+        snoise(params + dx) - snoise(params - dx),
+        snoise(params + dy) - snoise(params - dy),
+    ) / (2.0 * EPSILON);
+
+    let stretch = (channel.scale[0] * size.y) / (channel.scale[1] * size.x);
+    Vec2::new(gradient.y, -stretch * gradient.x)
+}
+
+fn make_fbm_noise(position: Vec2, offset: f32, channel: &NoiseChannel) -> Vec2 {
+    let mut noise = Vec2::ZERO;
+    let mut frequency = 1.0;
+    let mut amplitude = 1.0;
+    let mut total_amplitude = 0.0;
+    for octave in 0..channel.octaves.min(settings::MAX_NOISE_OCTAVES) {
+        let shift = 17.0 * octave as f32;
+        noise += amplitude * make_noise_pair((frequency * position + shift).extend(offset));
+        total_amplitude += amplitude;
+        frequency *= channel.lacunarity;
+        amplitude *= channel.gain;
+    }
+    noise / f32::max(total_amplitude, 1e-6)
+}
+
+fn make_domain_warped_noise(position: Vec2, offset: f32, channel: &NoiseChannel) -> Vec2 {
+    let warp = make_noise_pair((position + Vec2::new(5.2, 1.3)).extend(offset));
+    make_noise_pair((position + channel.warp * warp).extend(offset))
+}
+
+fn make_noise_pair(params: Vec3) -> Vec2 {
+    Vec2::new(snoise(params), snoise(params + Vec3::new(8.0, -8.0, 0.0)))
+}
+
+fn permute(x: Vec4) -> Vec4 {
+    (((x * 34.0) + 1.0) * x) % 289.0
+}
+
+// A direct port of the simplex noise in the shaders, down to the truncating
+// remainders, so that both produce the same values.
+pub fn snoise(v: Vec3) -> f32 {
+    const C: Vec2 = Vec2::new(1.0 / 6.0, 1.0 / 3.0);
+
+    // First corner
+    let mut i = (v + v.dot(Vec3::splat(C.y))).floor();
+    let x0 = v - i + i.dot(Vec3::splat(C.x));
+
+    // Other corners
+    let g = step(x0.yzx(), x0);
+    let l = 1.0 - g;
+    let i1 = g.min(l.zxy());
+    let i2 = g.max(l.zxy());
+
+    let x1 = x0 - i1 + C.x;
+    let x2 = x0 - i2 + C.y;
+    let x3 = x0 - 0.5;
+
+    // Permutations
+    i %= 289.0;
+    let p = permute(
+        permute(
+            permute(i.z + Vec4::new(0.0, i1.z, i2.z, 1.0)) + i.y + Vec4::new(0.0, i1.y, i2.y, 1.0),
+        ) + i.x
+            + Vec4::new(0.0, i1.x, i2.x, 1.0),
+    );
+
+    // Gradients: 7x7 points over a square, mapped onto an octahedron.
+    let j = p - 49.0 * (p * (1.0 / 49.0)).floor();
+
+    let x_ = (j * (1.0 / 7.0)).floor();
+    let y_ = (j - 7.0 * x_).floor();
+
+    let x = x_ * (2.0 / 7.0) + 0.5 / 7.0 - 1.0;
+    let y = y_ * (2.0 / 7.0) + 0.5 / 7.0 - 1.0;
+
+    let h = 1.0 - x.abs() - y.abs();
+
+    let b0 = Vec4::new(x.x, x.y, y.x, y.y);
+    let b1 = Vec4::new(x.z, x.w, y.z, y.w);
+
+    let s0 = b0.floor() * 2.0 + 1.0;
+    let s1 = b1.floor() * 2.0 + 1.0;
+    let sh = -step4(h, Vec4::ZERO);
+
+    let a0 = b0.xzyw() + s0.xzyw() * sh.xxyy();
+    let a1 = b1.xzyw() + s1.xzyw() * sh.zzww();
+
+    let gradients = [
+        a0.xy().extend(h.x),
+        a0.zw().extend(h.y),
+        a1.xy().extend(h.z),
+        a1.zw().extend(h.w),
+    ]
+    .map(|gradient| gradient / gradient.dot(gradient).sqrt());
+
+    // Mix final noise value
+    let offsets = [x0, x1, x2, x3];
+    let mut m = (0.6 - Vec4::from(offsets.map(|x| x.dot(x)))).max(Vec4::ZERO);
+    m = m * m;
+    m = m * m;
+
+    let px = Vec4::from([0, 1, 2, 3].map(|k| offsets[k].dot(gradients[k])));
+    42.0 * m.dot(px)
+}
+
+// WGSL’s `step(edge, x)`: 1 where x >= edge, 0 elsewhere.
+fn step(edge: Vec3, x: Vec3) -> Vec3 {
+    Vec3::select(x.cmpge(edge), Vec3::ONE, Vec3::ZERO)
+}
+
+fn step4(edge: Vec4, x: Vec4) -> Vec4 {
+    Vec4::select(x.cmpge(edge), Vec4::ONE, Vec4::ZERO)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn snoise_is_bounded_and_smooth() {
+        let samples = (0..1000).map(|i| {
+            // Away from the lattice points, where ties pick a degenerate simplex
+            let t = 0.05 + i as f32 * 0.173;
+            Vec3::new(t.sin() * 40.0, t * 0.7, t.cos() * 12.0)
+        });
+
+        for position in samples {
+            let value = snoise(position);
+            assert!(value.abs() <= 1.0, "snoise({}) = {}", position, value);
+
+            let nearby = snoise(position + Vec3::splat(1e-3));
+            assert!((value - nearby).abs() < 0.05);
+        }
+    }
+
+    fn channel(kind: settings::NoiseKind) -> NoiseChannel {
+        let channel_settings = settings::Noise {
+            kind,
+            ..settings::Settings::default().noise_channels[0].clone()
+        };
+        NoiseChannel::new(grid::ScalingRatio::new(2, 1), &channel_settings)
+    }
+
+    #[test]
+    fn curl_noise_is_divergence_free() {
+        let (width, height) = (128, 64);
+        let noise = generate(width, height, &[channel(settings::NoiseKind::Curl)], 1.0);
+        let simplex = generate(width, height, &[channel(settings::NoiseKind::Simplex)], 1.0);
+
+        // Central differences on the texture’s grid
+        let total_divergence = |noise: &Field<[f32; 2]>| -> f32 {
+            let mut total = 0.0;
+            for y in 1..height - 1 {
+                for x in 1..width - 1 {
+                    let divergence = 0.5 * (noise.get(x + 1, y)[0] - noise.get(x - 1, y)[0])
+                        + 0.5 * (noise.get(x, y + 1)[1] - noise.get(x, y - 1)[1]);
+                    total += divergence.abs();
+                }
+            }
+            total
+        };
+        let total_magnitude = |noise: &Field<[f32; 2]>| -> f32 {
+            noise.data().iter().map(|v| Vec2::from(*v).length()).sum()
+        };
+
+        // Compare the divergence relative to the size of the flow.
+        let curl = total_divergence(&noise) / total_magnitude(&noise);
+        let simplex = total_divergence(&simplex) / total_magnitude(&simplex);
+        assert!(curl < 0.05 * simplex, "{} vs {}", curl, simplex);
+    }
+
+    #[test]
+    fn every_kind_makes_noise() {
+        for kind in [
+            settings::NoiseKind::Simplex,
+            settings::NoiseKind::Curl,
+            settings::NoiseKind::Fbm {
+                octaves: 4,
+                lacunarity: 2.0,
+                gain: 0.5,
+            },
+            settings::NoiseKind::DomainWarped { warp: 1.5 },
+        ] {
+            let noise = generate(64, 32, &[channel(kind)], 1.0);
+            for value in noise.data() {
+                assert!(value.iter().all(|v| v.is_finite()), "{:?}", kind);
+            }
+            assert!(noise.data().iter().any(|&v| v != [0.0; 2]), "{:?}", kind);
+        }
+    }
+}
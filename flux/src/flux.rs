@@ -1,11 +1,13 @@
-use crate::{grid, render, rng, settings};
+use crate::{audio, grid, palette, playlist, render, rng, settings, snapshot};
 use settings::Settings;
 
+use std::collections::VecDeque;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
 
 // The time at which the animation timer will reset to zero.
-const MAX_ELAPSED_TIME: f32 = 1000.0;
+pub(crate) const MAX_ELAPSED_TIME: f32 = 1000.0;
 const MAX_FRAME_TIME: f32 = 1.0 / 10.0;
 
 pub struct Flux {
@@ -21,23 +23,86 @@ pub struct Flux {
 
     pub color_image: Arc<Mutex<Option<image::RgbaImage>>>,
 
+    obstacle_image: Option<image::RgbaImage>,
+    // The file the obstacle image comes from, if any.
+    obstacle_image_source: Option<PathBuf>,
+
     // A timestamp in milliseconds. Either host or video time.
     last_timestamp: f64,
 
+    // In deterministic mode, every frame advances the simulation by this many
+    // seconds, whatever the timestamps.
+    deterministic_timestep: Option<f32>,
+
     // A local animation timer in seconds that resets at MAX_ELAPSED_TIME.
     elapsed_time: f32,
 
     fluid_frame_time: f32,
+
+    // Impulses waiting for the next fluid step
+    impulses: VecDeque<render::fluid::Impulse>,
+
+    // Created when the host first feeds audio
+    audio: Option<audio::Analyzer>,
 }
 
 impl Flux {
-    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, settings: &Arc<Settings>) {
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &Arc<Settings>,
+    ) -> Result<(), Problem> {
+        // Do everything that can fail first, so that bad settings leave the
+        // current state untouched.
+        settings.validate()?;
+        let color_image = self.lines.load_color_image(device, queue, settings)?;
+        let obstacle_image = self.load_obstacle_image(settings)?;
+
+        let grid_changed = settings.grid_spacing != self.settings.grid_spacing;
+        let grid = grid::Grid::new(
+            self.logical_size.width,
+            self.logical_size.height,
+            settings.grid_spacing,
+        );
+
+        self.lines.update(
+            device,
+            queue,
+            self.logical_size,
+            &grid,
+            settings,
+            color_image,
+        );
+        if grid_changed {
+            self.lines.set_grid(device, queue, &grid);
+        }
+
+        self.grid = grid;
         self.settings = Arc::clone(settings);
+        self.set_obstacle_source(obstacle_image);
         self.fluid
             .update(device, queue, self.grid.scaling_ratio, &self.settings);
         self.noise_generator.update(&self.settings);
-        self.lines
-            .update(device, queue, self.logical_size, &self.grid, &self.settings);
+        if let Some(analyzer) = &mut self.audio {
+            analyzer.update(&self.settings.audio);
+        }
+        self.resize_simulation(device, queue);
+
+        Ok(())
+    }
+
+    // Set the image for the obstacle in the settings. Hosts that can’t read
+    // files, like the web, fetch the image themselves and pass it here.
+    pub fn set_obstacle_image(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: image::RgbaImage,
+    ) {
+        self.obstacle_image = Some(image);
+        self.obstacle_image_source = None;
+        self.update_obstacle(device, queue);
     }
 
     pub fn sample_colors_from_image(
@@ -45,9 +110,19 @@ impl Flux {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         image: &image::RgbaImage,
+    ) -> Result<(), Problem> {
+        self.lines
+            .set_color_image(device, queue, image, self.settings.image_color_mode)?;
+        Ok(())
+    }
+
+    pub fn sample_colors_from_palette(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        palette: &settings::Palette,
     ) {
-        let texture_view = render::color::load_color_texture(device, queue, image);
-        self.sample_colors_from_texture_view(device, queue, texture_view);
+        self.lines.set_palette(device, queue, palette);
     }
 
     pub fn sample_colors_from_texture_view(
@@ -60,6 +135,103 @@ impl Flux {
             .update_color_bindings(device, queue, Some(texture_view), None);
     }
 
+    // Stir the fluid around a point, like dragging a finger through it.
+    //
+    // The position and radius are in logical pixels, measured from the top-left
+    // corner, and the velocity is in logical pixels per second. The impulse
+    // applies on the next fluid step.
+    pub fn add_impulse(&mut self, position: [f32; 2], velocity: [f32; 2], radius: f32) {
+        let width = self.logical_size.width as f32;
+        let height = self.logical_size.height as f32;
+        let zoom = self.settings.view_scale;
+
+        // The lines cover the view, zoomed in around its center, with the
+        // y-axis pointing up.
+        let to_texture = |x: f32, y: f32| {
+            [
+                0.5 + (x / width - 0.5) / zoom,
+                0.5 - (y / height - 0.5) / zoom,
+            ]
+        };
+
+        // Drop the oldest impulses if the host sends them faster than we step.
+        if self.impulses.len() == render::fluid::MAX_IMPULSES {
+            self.impulses.pop_front();
+        }
+
+        self.impulses.push_back(render::fluid::Impulse {
+            position: to_texture(position[0], position[1]),
+            // Measured in view heights per second, so that a brisk drag across
+            // the view stretches the lines out.
+            velocity: [velocity[0] / height, -velocity[1] / height],
+            radius: [radius / (zoom * width), radius / (zoom * height)],
+        });
+    }
+
+    // Listen to audio, to drive the noise and the lines with the mappings in
+    // the audio settings.
+    //
+    // Pass mono samples in [-1, 1] as they play. Hosts with a file can feed it
+    // in step with the timestamps.
+    pub fn feed_audio(&mut self, samples: &[f32], sample_rate: u32) {
+        let analyzer = match &mut self.audio {
+            Some(analyzer) if analyzer.get_sample_rate() == sample_rate => analyzer,
+            _ => {
+                log::info!("🎵 Listening to audio at {} Hz", sample_rate);
+                self.audio
+                    .insert(audio::Analyzer::new(sample_rate, &self.settings.audio))
+            }
+        };
+
+        analyzer.feed(samples);
+    }
+
+    // Check that the adapter can run the simulation with the given features
+    // and limits, before requesting a device.
+    pub fn check_adapter(
+        adapter: &wgpu::Adapter,
+        features: wgpu::Features,
+        limits: &wgpu::Limits,
+    ) -> Result<(), Problem> {
+        let missing_features = features - adapter.features();
+        if !missing_features.is_empty() {
+            return Err(Problem::MissingFeatures(missing_features));
+        }
+
+        let mut missing_limit = None;
+        limits.check_limits_with_fail_fn(&adapter.limits(), true, |name, required, allowed| {
+            missing_limit = Some(Problem::MissingLimit {
+                name,
+                required,
+                allowed,
+            });
+        });
+        if let Some(problem) = missing_limit {
+            return Err(problem);
+        }
+
+        // The fluid simulation writes to float storage textures. Downlevel
+        // backends, like GL, can’t do that.
+        let format = wgpu::TextureFormat::Rg32Float;
+        let usages = wgpu::TextureUsages::STORAGE_BINDING;
+        if !adapter
+            .get_texture_format_features(format)
+            .allowed_usages
+            .contains(usages)
+        {
+            return Err(Problem::UnsupportedTextureFormat { format, usages });
+        }
+
+        Ok(())
+    }
+
+    // Report a lost device. The callback may be called from any thread.
+    pub fn on_device_lost(device: &wgpu::Device, callback: impl Fn(Problem) + Send + 'static) {
+        device.set_device_lost_callback(move |reason, message| {
+            callback(Problem::DeviceLost { reason, message })
+        });
+    }
+
     pub fn new(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -69,9 +241,11 @@ impl Flux {
         physical_width: u32,
         physical_height: u32,
         settings: &Arc<Settings>,
-    ) -> Result<Flux, String> {
+    ) -> Result<Flux, Problem> {
         log::info!("✨ Initialising Flux");
 
+        settings.validate()?;
+
         rng::init_from_seed(&settings.seed);
 
         let logical_size = wgpu::Extent3d {
@@ -99,7 +273,7 @@ impl Flux {
             logical_size,
             &grid,
             settings,
-        );
+        )?;
 
         let mut noise_generator_builder = render::noise::NoiseGeneratorBuilder::new(
             2 * settings.fluid_size,
@@ -119,10 +293,11 @@ impl Flux {
                 ("noise", noise_generator.get_noise_texture_view()),
                 ("pressure", fluid.get_pressure_texture_view()),
                 ("divergence", fluid.get_divergence_texture_view()),
+                ("vorticity", fluid.get_vorticity_texture_view()),
             ],
         );
 
-        Ok(Flux {
+        let mut flux = Flux {
             settings: Arc::clone(settings),
             logical_size,
             physical_size,
@@ -134,11 +309,25 @@ impl Flux {
             debug_texture,
             color_image: Arc::new(Mutex::new(None)),
 
+            obstacle_image: None,
+            obstacle_image_source: None,
+
             last_timestamp: 0.0,
+            deterministic_timestep: None,
             elapsed_time: 0.0,
 
             fluid_frame_time: 0.0,
-        })
+
+            impulses: VecDeque::new(),
+
+            audio: None,
+        };
+
+        let obstacle_image = flux.load_obstacle_image(settings)?;
+        flux.set_obstacle_source(obstacle_image);
+        flux.update_obstacle(device, queue);
+
+        Ok(flux)
     }
 
     pub fn resize(
@@ -152,7 +341,6 @@ impl Flux {
     ) {
         let grid = grid::Grid::new(logical_width, logical_height, self.settings.grid_spacing);
 
-        // TODO: fetch line state from GPU and resample for new grid
         let logical_size = wgpu::Extent3d {
             width: logical_width,
             height: logical_height,
@@ -171,12 +359,145 @@ impl Flux {
         self.logical_size = logical_size;
         self.physical_size = physical_size;
 
-        // self.fluid.resize(device, self.grid.scaling_ratio);
+        self.resize_simulation(device, queue);
+    }
+
+    // Match the fluid and noise textures to the grid and the fluid size.
+    fn resize_simulation(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.fluid.resize(
+            device,
+            queue,
+            self.grid.scaling_ratio,
+            self.settings.fluid_size,
+        );
         self.noise_generator.resize(
             device,
             2 * self.settings.fluid_size,
             self.grid.scaling_ratio,
         );
+
+        self.debug_texture.set_texture_views(
+            device,
+            &[
+                ("fluid", self.fluid.get_velocity_texture_view()),
+                ("noise", self.noise_generator.get_noise_texture_view()),
+                ("pressure", self.fluid.get_pressure_texture_view()),
+                ("divergence", self.fluid.get_divergence_texture_view()),
+                ("vorticity", self.fluid.get_vorticity_texture_view()),
+            ],
+        );
+
+        self.update_obstacle(device, queue);
+    }
+
+    // Load the image for the obstacle in the settings, unless it’s already
+    // loaded. `set_obstacle_source` applies it once nothing else can fail.
+    //
+    // On the web, the host passes the image to `set_obstacle_image` instead.
+    fn load_obstacle_image(
+        &self,
+        settings: &Settings,
+    ) -> Result<Option<(PathBuf, image::RgbaImage)>, Problem> {
+        match &settings.obstacle {
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(obstacle) if self.obstacle_image_source.as_ref() != Some(&obstacle.image) => {
+                let image = render::obstacle::read_image(&obstacle.image)?;
+                Ok(Some((obstacle.image.clone(), image)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Swap in a freshly loaded obstacle image, or drop the image if the
+    // current settings have no obstacle.
+    fn set_obstacle_source(&mut self, obstacle_image: Option<(PathBuf, image::RgbaImage)>) {
+        if self.settings.obstacle.is_none() {
+            self.obstacle_image = None;
+            self.obstacle_image_source = None;
+        } else if let Some((source, image)) = obstacle_image {
+            self.obstacle_image = Some(image);
+            self.obstacle_image_source = Some(source);
+        }
+    }
+
+    // Rasterize the obstacle onto the fluid, which changes with the fluid size,
+    // the view and the obstacle settings.
+    fn update_obstacle(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let size = self.fluid.get_fluid_size();
+        let mask = match (&self.settings.obstacle, &self.obstacle_image) {
+            (Some(obstacle), Some(image)) => render::obstacle::rasterize(
+                image,
+                obstacle,
+                [size.width, size.height],
+                self.grid.aspect_ratio,
+                self.settings.view_scale,
+            ),
+            _ => vec![0.0; (size.width * size.height) as usize],
+        };
+
+        self.fluid.write_obstacle(queue, &mask);
+        self.lines
+            .set_obstacle_texture_view(device, self.fluid.get_obstacle_texture_view());
+    }
+
+    // Capture the state of the simulation, to resume it later with `restore`.
+    //
+    // This waits for the GPU to copy the fluid and lines back, so it only works
+    // where buffers can be mapped synchronously, which rules out the web.
+    pub fn snapshot(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> snapshot::Snapshot {
+        snapshot::Snapshot {
+            fluid: self.fluid.snapshot(device, queue),
+            lines: self.lines.snapshot(device, queue),
+            noise: self.noise_generator.snapshot(),
+            elapsed_time: self.elapsed_time,
+            fluid_frame_time: self.fluid_frame_time,
+            rng_state: rng::state(),
+            impulses: self.impulses.iter().copied().collect(),
+            audio: self.audio.as_ref().map(audio::Analyzer::snapshot),
+        }
+    }
+
+    // Resume the simulation from a snapshot.
+    //
+    // The simulation needs the same size and settings as when the snapshot was
+    // taken. The host clock isn’t part of the snapshot: keep passing timestamps
+    // from the current clock to `animate`.
+    pub fn restore(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        snapshot: &snapshot::Snapshot,
+    ) -> Result<(), Problem> {
+        // Check everything before touching any state.
+        snapshot::check(
+            "fluid size",
+            snapshot.fluid.size,
+            [
+                self.fluid.get_fluid_size().width,
+                self.fluid.get_fluid_size().height,
+            ],
+        )?;
+        snapshot::check(
+            "line count",
+            snapshot.lines.line_count,
+            self.grid.line_count,
+        )?;
+        let audio = snapshot
+            .audio
+            .as_ref()
+            .map(|state| audio::Analyzer::restore(state, &self.settings.audio))
+            .transpose()?;
+
+        self.noise_generator.restore(&snapshot.noise)?;
+        self.fluid.restore(queue, &snapshot.fluid)?;
+        self.lines.restore(device, queue, &snapshot.lines)?;
+        self.elapsed_time = snapshot.elapsed_time;
+        self.fluid_frame_time = snapshot.fluid_frame_time;
+        rng::set_state(snapshot.rng_state);
+        self.impulses = snapshot.impulses.iter().copied().collect();
+        self.audio = audio;
+
+        Ok(())
     }
 
     pub fn animate(
@@ -200,12 +521,40 @@ impl Flux {
         timestamp: f64,
     ) {
         // The delta time in seconds
-        let timestep = f32::min(
-            MAX_FRAME_TIME,
-            0.001 * (timestamp - self.last_timestamp) as f32,
-        );
-
+        let timestep = self.deterministic_timestep.unwrap_or_else(|| {
+            f32::min(
+                MAX_FRAME_TIME,
+                0.001 * (timestamp - self.last_timestamp) as f32,
+            )
+        });
         self.last_timestamp = timestamp;
+
+        self.step(device, queue, encoder, timestep);
+    }
+
+    // Switch to deterministic mode, where each call to `compute` or `animate`
+    // advances the simulation by a fixed `timestep` in seconds, ignoring the
+    // timestamps. Pass `None` to follow the timestamps again.
+    //
+    // Together with `step`, this makes the simulation depend only on the seed,
+    // the settings and the number of steps. Two runs with the same `seed` and
+    // settings produce bit-identical lines on the same adapter. Without a seed,
+    // the initial noise offsets are random.
+    pub fn set_deterministic_timestep(&mut self, timestep: Option<f32>) {
+        self.deterministic_timestep = timestep;
+    }
+
+    // Advance the simulation by `timestep` seconds.
+    //
+    // Unlike `compute`, this doesn’t look at the timestamps or clamp the
+    // timestep, so the result only depends on the sequence of timesteps.
+    pub fn step(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        timestep: f32,
+    ) {
         self.elapsed_time += timestep;
         self.fluid_frame_time += timestep;
 
@@ -215,6 +564,12 @@ impl Flux {
             self.elapsed_time = timer_overflow;
         }
 
+        if let Some(analyzer) = &self.audio {
+            let modulation = audio::Modulation::new(analyzer.get_levels(), &self.settings);
+            self.noise_generator.set_modulation(&modulation);
+            self.lines.set_modulation(&modulation);
+        }
+
         while self.fluid_frame_time >= self.settings.fluid_timestep {
             self.noise_generator
                 .update_buffers(queue, self.settings.fluid_timestep);
@@ -238,6 +593,20 @@ impl Flux {
                 self.fluid.get_fluid_size(),
             );
 
+            // The debug view shows the vorticity even without the confinement.
+            let confine_vorticity = self.settings.vorticity_strength > 0.0;
+            if confine_vorticity || self.settings.mode == settings::Mode::DebugVorticity {
+                self.fluid.calculate_vorticity(&mut cpass);
+            }
+            if confine_vorticity {
+                self.fluid.confine_vorticity(&mut cpass);
+            }
+
+            // Splat before the projection, so that it cleans up the impulses.
+            self.fluid
+                .add_impulses(queue, &mut cpass, self.impulses.make_contiguous());
+            self.impulses.clear();
+
             self.fluid.calculate_divergence(&mut cpass);
             self.fluid.solve_pressure(queue, &mut cpass);
             self.fluid.subtract_gradient(&mut cpass);
@@ -311,6 +680,10 @@ impl Flux {
                     self.debug_texture
                         .draw_texture(device, &mut rpass, "divergence");
                 }
+                DebugVorticity => {
+                    self.debug_texture
+                        .draw_texture(device, &mut rpass, "vorticity");
+                }
             };
         }
 
@@ -318,21 +691,51 @@ impl Flux {
     }
 }
 
-// #[derive(Debug)]
-// pub enum Problem {
-//     ReadSettings(String),
-//     ReadImage(std::io::Error),
-//     DecodeColorTexture(image::ImageError),
-//     Render(render::Problem),
-// }
-//
-// impl fmt::Display for Problem {
-//     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//         match self {
-//             Problem::ReadSettings(msg) => write!(f, "{}", msg),
-//             Problem::ReadImage(msg) => write!(f, "{}", msg),
-//             Problem::DecodeColorTexture(msg) => write!(f, "Failed to decode image: {}", msg),
-//             Problem::Render(render_msg) => write!(f, "{}", render_msg),
-//         }
-//     }
-// }
+#[derive(Debug, thiserror::Error)]
+pub enum Problem {
+    #[error(transparent)]
+    InvalidSettings(#[from] settings::ValidationError),
+
+    #[error(transparent)]
+    ColorImage(#[from] render::color::Problem),
+
+    #[error(transparent)]
+    Obstacle(#[from] render::obstacle::Problem),
+
+    #[error(transparent)]
+    Palette(#[from] palette::Problem),
+
+    #[error(transparent)]
+    Playlist(#[from] playlist::Problem),
+
+    #[error(transparent)]
+    Snapshot(#[from] snapshot::Problem),
+
+    #[error("Failed to find an appropriate adapter")]
+    NoAdapter,
+
+    #[error("The adapter doesn’t support the required features: {0:?}")]
+    MissingFeatures(wgpu::Features),
+
+    #[error("The adapter doesn’t support the required limit `{name}` (required: {required}, allowed: {allowed})")]
+    MissingLimit {
+        name: &'static str,
+        required: u64,
+        allowed: u64,
+    },
+
+    #[error("The adapter doesn’t support {usages:?} for {format:?} textures")]
+    UnsupportedTextureFormat {
+        format: wgpu::TextureFormat,
+        usages: wgpu::TextureUsages,
+    },
+
+    #[error("Failed to create device: {0}")]
+    RequestDevice(#[from] wgpu::RequestDeviceError),
+
+    #[error("Lost the device ({reason:?}): {message}")]
+    DeviceLost {
+        reason: wgpu::DeviceLostReason,
+        message: String,
+    },
+}
@@ -0,0 +1,269 @@
+// Render Flux without a window or surface.
+//
+// The headless context owns its own render target and copies every rendered
+// frame back to the CPU. Useful for thumbnails, exports, and tests on machines
+// without a display.
+
+use crate::{render::readback, settings::Settings, Flux, Problem};
+
+use std::sync::Arc;
+
+pub struct Headless {
+    flux: Flux,
+    size: wgpu::Extent3d,
+
+    texture: wgpu::Texture,
+    texture_view: wgpu::TextureView,
+}
+
+impl Headless {
+    // A non-srgb format, as we do linear math in the shaders.
+    pub const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    // Request a device suitable for headless rendering.
+    //
+    // Set `force_fallback_adapter` to render on a software adapter, like Mesa’s
+    // lavapipe or llvmpipe.
+    pub async fn request_device(
+        force_fallback_adapter: bool,
+    ) -> Result<(wgpu::Device, wgpu::Queue), Problem> {
+        let wgpu_instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
+        let adapter = wgpu_instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter,
+                compatible_surface: None,
+            })
+            .await
+            .ok_or(Problem::NoAdapter)?;
+
+        log::debug!("{:?}", adapter.get_info());
+
+        let limits = wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits());
+        // Software adapters may not advertise FLOAT32_FILTERABLE, but can still
+        // filter float textures through adapter-specific format features.
+        let features = (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+            | wgpu::Features::FLOAT32_FILTERABLE)
+            & adapter.features();
+        Flux::check_adapter(&adapter, features, &limits)?;
+
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("flux:headless"),
+                    required_features: features,
+                    required_limits: limits,
+                    memory_hints: wgpu::MemoryHints::Performance,
+                },
+                None,
+            )
+            .await
+            .map_err(Problem::from)
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        logical_width: u32,
+        logical_height: u32,
+        physical_width: u32,
+        physical_height: u32,
+        settings: &Arc<Settings>,
+    ) -> Result<Headless, Problem> {
+        let flux = Flux::new(
+            device,
+            queue,
+            Self::TEXTURE_FORMAT,
+            logical_width,
+            logical_height,
+            physical_width,
+            physical_height,
+            settings,
+        )?;
+
+        let size = wgpu::Extent3d {
+            width: physical_width,
+            height: physical_height,
+            depth_or_array_layers: 1,
+        };
+        let (texture, texture_view) = create_render_target(device, &size);
+
+        Ok(Headless {
+            flux,
+            size,
+
+            texture,
+            texture_view,
+        })
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &Arc<Settings>,
+    ) -> Result<(), Problem> {
+        self.flux.update(device, queue, settings)
+    }
+
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        logical_width: u32,
+        logical_height: u32,
+        physical_width: u32,
+        physical_height: u32,
+    ) {
+        self.flux.resize(
+            device,
+            queue,
+            logical_width,
+            logical_height,
+            physical_width,
+            physical_height,
+        );
+
+        let size = wgpu::Extent3d {
+            width: physical_width,
+            height: physical_height,
+            depth_or_array_layers: 1,
+        };
+        if size != self.size {
+            let (texture, texture_view) = create_render_target(device, &size);
+            self.size = size;
+            self.texture = texture;
+            self.texture_view = texture_view;
+        }
+    }
+
+    pub fn flux(&self) -> &Flux {
+        &self.flux
+    }
+
+    pub fn flux_mut(&mut self) -> &mut Flux {
+        &mut self.flux
+    }
+
+    // Advance the simulation to `timestamp`, in milliseconds, and return the
+    // rendered frame.
+    pub fn animate(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        timestamp: f64,
+    ) -> image::RgbaImage {
+        self.compute(device, queue, timestamp);
+        self.render(device, queue)
+    }
+
+    pub fn compute(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, timestamp: f64) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("flux:headless:compute"),
+        });
+        self.flux.compute(device, queue, &mut encoder, timestamp);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    // Advance the simulation by exactly `timestep` seconds. See `Flux::step`.
+    pub fn step(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, timestep: f32) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("flux:headless:step"),
+        });
+        self.flux.step(device, queue, &mut encoder, timestep);
+        queue.submit(Some(encoder.finish()));
+    }
+
+    pub fn render(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> image::RgbaImage {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("flux:headless:render"),
+        });
+
+        self.flux
+            .render(device, queue, &mut encoder, &self.texture_view, None);
+
+        queue.submit(Some(encoder.finish()));
+
+        let pixels = readback::read_texture(device, queue, &self.texture);
+        image::RgbaImage::from_raw(self.size.width, self.size.height, pixels)
+            .expect("Frame buffer has the wrong size")
+    }
+}
+
+fn create_render_target(
+    device: &wgpu::Device,
+    size: &wgpu::Extent3d,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("texture:headless"),
+        size: *size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: Headless::TEXTURE_FORMAT,
+        view_formats: &[],
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    });
+
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("view:headless"),
+        ..Default::default()
+    });
+
+    (texture, texture_view)
+}
+
+// A device for the GPU tests, which fail without an adapter. They’re ignored by
+// default: run them with `cargo test -- --include-ignored` on a GPU, or on a
+// software adapter, like lavapipe, in CI.
+#[cfg(test)]
+pub(crate) fn test_device() -> (wgpu::Device, wgpu::Queue) {
+    pollster::block_on(Headless::request_device(false))
+        .unwrap_or_else(|err| panic!("The GPU tests need an adapter: {}", err))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs a GPU or lavapipe"]
+    fn renders_frames_without_a_surface() {
+        let (device, queue) = test_device();
+
+        let settings = Arc::new(Settings {
+            seed: Some("headless".to_string()),
+            ..Default::default()
+        });
+        let mut headless = Headless::new(&device, &queue, 200, 150, 400, 300, &settings).unwrap();
+
+        let mut frame = headless.animate(&device, &queue, 0.0);
+        for i in 1..30 {
+            frame = headless.animate(&device, &queue, f64::from(i) * 1000.0 / 60.0);
+        }
+
+        assert_eq!(frame.dimensions(), (400, 300));
+        assert!(frame.pixels().any(|pixel| pixel.0[..3] != [0, 0, 0]));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU or lavapipe"]
+    fn steps_deterministically() {
+        let (device, queue) = test_device();
+
+        let settings = Arc::new(Settings {
+            seed: Some("deterministic".to_string()),
+            ..Default::default()
+        });
+        let run = || {
+            let mut headless =
+                Headless::new(&device, &queue, 200, 150, 200, 150, &settings).unwrap();
+            for _ in 0..40 {
+                headless.step(&device, &queue, 1.0 / 60.0);
+            }
+            headless.flux().snapshot(&device, &queue).lines.line_buffers
+        };
+
+        assert_eq!(run(), run());
+    }
+}
@@ -1,8 +1,16 @@
+pub mod audio;
+pub mod cpu;
 mod flux;
 mod grid;
+mod headless;
+pub mod palette;
+pub mod playlist;
 pub mod render;
 mod rng;
 pub mod settings;
+pub mod snapshot;
 
-pub use flux::Flux;
+pub use flux::{Flux, Problem};
+pub use headless::Headless;
 pub use settings::Settings;
+pub use snapshot::Snapshot;
@@ -0,0 +1,510 @@
+// Import color palettes from common palette files, or extract them from
+// images.
+//
+// Supported formats:
+//   - GIMP palettes (.gpl)
+//   - Plain text and CSS lists of hex colors (.txt, .hex, .css)
+//   - Adobe Swatch Exchange (.ase)
+
+use crate::settings::{ColorStop, Palette};
+
+use image::RgbaImage;
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Problem {
+    #[error("Failed to read palette: {0}")]
+    Read(#[from] std::io::Error),
+
+    #[error("Unknown palette format")]
+    UnknownFormat,
+
+    #[error("The palette isn’t valid UTF-8")]
+    Encoding(#[from] std::str::Utf8Error),
+
+    #[error("Not a GIMP palette")]
+    NotGpl,
+
+    #[error("Invalid color on line {line}: `{text}`")]
+    InvalidGplColor { line: usize, text: String },
+
+    #[error("Not an Adobe Swatch Exchange file")]
+    NotAse,
+
+    #[error("The Adobe Swatch Exchange file ends unexpectedly")]
+    TruncatedAse,
+
+    #[error("The palette doesn’t contain any colors")]
+    NoColors,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Format {
+    Gpl,
+    Hex,
+    Ase,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gpl" => Some(Format::Gpl),
+            "txt" | "hex" | "css" => Some(Format::Hex),
+            "ase" => Some(Format::Ase),
+            _ => None,
+        }
+    }
+}
+
+pub fn read(path: &Path) -> Result<Palette, Problem> {
+    let format = Format::from_path(path).ok_or(Problem::UnknownFormat)?;
+    let bytes = std::fs::read(path)?;
+    parse(format, &bytes)
+}
+
+pub fn parse(format: Format, bytes: &[u8]) -> Result<Palette, Problem> {
+    let colors = match format {
+        Format::Gpl => parse_gpl(std::str::from_utf8(bytes)?)?,
+        Format::Hex => parse_hex_list(std::str::from_utf8(bytes)?),
+        Format::Ase => parse_ase(bytes)?,
+    };
+
+    if colors.is_empty() {
+        return Err(Problem::NoColors);
+    }
+
+    Ok(Palette {
+        stops: colors
+            .into_iter()
+            .map(|color| ColorStop {
+                color,
+                position: None,
+            })
+            .collect(),
+        ..Default::default()
+    })
+}
+
+// GIMP palettes start with a `GIMP Palette` header, followed by optional
+// `Name:` and `Columns:` fields, comments, and one `R G B [name]` color per
+// line.
+pub fn parse_gpl(text: &str) -> Result<Vec<[f32; 3]>, Problem> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == "GIMP Palette" => (),
+        _ => return Err(Problem::NotGpl),
+    }
+
+    let mut colors = Vec::new();
+    for (index, line) in lines {
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("Name:")
+            || line.starts_with("Columns:")
+        {
+            continue;
+        }
+
+        let invalid_color = || Problem::InvalidGplColor {
+            line: index + 1,
+            text: line.to_string(),
+        };
+        let mut channels = line.split_whitespace().map(|channel| channel.parse::<u8>());
+        let mut next_channel = || match channels.next() {
+            Some(Ok(channel)) => Ok(f32::from(channel) / 255.0),
+            _ => Err(invalid_color()),
+        };
+        colors.push([next_channel()?, next_channel()?, next_channel()?]);
+    }
+
+    Ok(colors)
+}
+
+// Find hex colors, like `#ff8000` or `#f80`, anywhere in the text. This
+// covers CSS files and lists of colors. Files without any `#` may list bare
+// six-digit hex colors instead, one per line, like Lospec’s .hex files.
+pub fn parse_hex_list(text: &str) -> Vec<[f32; 3]> {
+    let tokens = text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '#'));
+
+    if text.contains('#') {
+        tokens
+            .filter_map(|token| token.strip_prefix('#'))
+            .filter_map(parse_hex_color)
+            .collect()
+    } else {
+        tokens
+            .filter(|token| token.len() == 6 || token.len() == 8)
+            .filter_map(parse_hex_color)
+            .collect()
+    }
+}
+
+// Parse `rgb`, `rgba`, `rrggbb`, or `rrggbbaa`. Alpha is ignored.
+fn parse_hex_color(hex: &str) -> Option<[f32; 3]> {
+    if !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let channel = |start: usize, len: usize| {
+        let value = u8::from_str_radix(&hex[start..start + len], 16).ok()?;
+        // Expand short channels: `f` => `ff`.
+        let value = if len == 1 { value * 17 } else { value };
+        Some(f32::from(value) / 255.0)
+    };
+
+    match hex.len() {
+        3 | 4 => Some([channel(0, 1)?, channel(1, 1)?, channel(2, 1)?]),
+        6 | 8 => Some([channel(0, 2)?, channel(2, 2)?, channel(4, 2)?]),
+        _ => None,
+    }
+}
+
+// Enough pixels to find the dominant colors of a photo.
+const MAX_SAMPLES: usize = 16384;
+
+// Extract up to `size` dominant colors from an image with median cut, ordered
+// by hue to form a color wheel.
+pub fn extract_from_image(image: &RgbaImage, size: usize) -> Palette {
+    let stride = (image.pixels().len() / MAX_SAMPLES).max(1);
+    let mut samples = image
+        .pixels()
+        .step_by(stride)
+        // Ignore mostly transparent pixels.
+        .filter(|pixel| pixel.0[3] >= 128)
+        .map(|pixel| [pixel.0[0], pixel.0[1], pixel.0[2]])
+        .collect::<Vec<_>>();
+    if samples.is_empty() {
+        samples.push([0, 0, 0]);
+    }
+
+    let mut boxes = vec![samples];
+    while boxes.len() < size.max(1) {
+        // Split the box with the widest spread of colors, weighted by the
+        // number of pixels in it.
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (index, channel, range as usize * pixels.len())
+            })
+            .filter(|&(_, _, score)| score > 0)
+            .max_by_key(|&(_, _, score)| score)
+            .map(|(index, channel, _)| (index, channel))
+        else {
+            break;
+        };
+
+        let mut pixels = boxes.swap_remove(index);
+        pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+
+        // Split at the median, but keep pixels of the same value together.
+        let median = pixels[pixels.len() / 2][channel];
+        let mut split = pixels.partition_point(|pixel| pixel[channel] <= median);
+        if split == pixels.len() {
+            split = pixels.partition_point(|pixel| pixel[channel] < median);
+        }
+        let upper = pixels.split_off(split);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    let mut colors = boxes
+        .iter()
+        .map(|pixels| {
+            let mut sum = [0u64; 3];
+            for pixel in pixels {
+                for (total, channel) in sum.iter_mut().zip(pixel) {
+                    *total += u64::from(*channel);
+                }
+            }
+            sum.map(|total| total as f32 / (255.0 * pixels.len() as f32))
+        })
+        .collect::<Vec<_>>();
+    colors.sort_by(|a, b| hue(a).total_cmp(&hue(b)));
+
+    Palette {
+        stops: colors
+            .into_iter()
+            .map(|color| ColorStop {
+                color,
+                position: None,
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let (min, max) = pixels.iter().fold((u8::MAX, u8::MIN), |(min, max), pixel| {
+                (min.min(pixel[channel]), max.max(pixel[channel]))
+            });
+            (channel, max.saturating_sub(min))
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap()
+}
+
+// The HSV hue in [0, 6). Grays have a hue of 0.
+fn hue([r, g, b]: &[f32; 3]) -> f32 {
+    let max = r.max(*g).max(*b);
+    let min = r.min(*g).min(*b);
+    let chroma = max - min;
+    if chroma == 0.0 {
+        0.0
+    } else if max == *r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == *g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    }
+}
+
+const ASE_SIGNATURE: &[u8] = b"ASEF";
+const ASE_COLOR_ENTRY: u16 = 0x0001;
+
+// Adobe Swatch Exchange files are big-endian. After the header, the file
+// contains a list of blocks. Only color entries are read; groups are
+// flattened.
+pub fn parse_ase(bytes: &[u8]) -> Result<Vec<[f32; 3]>, Problem> {
+    let mut reader = AseReader { bytes };
+
+    if reader.take(4)? != ASE_SIGNATURE {
+        return Err(Problem::NotAse);
+    }
+    let _version = reader.take(4)?;
+    let block_count = reader.read_u32()?;
+
+    let mut colors = Vec::new();
+    for _ in 0..block_count {
+        let block_type = reader.read_u16()?;
+        let block_length = reader.read_u32()? as usize;
+        let mut block = AseReader {
+            bytes: reader.take(block_length)?,
+        };
+
+        if block_type != ASE_COLOR_ENTRY {
+            continue;
+        }
+
+        // The name is a null-terminated UTF-16 string, prefixed with its
+        // length in code units.
+        let name_length = block.read_u16()? as usize;
+        block.take(2 * name_length)?;
+
+        let model = block.take(4)?;
+        let color = match model {
+            b"RGB " => [block.read_f32()?, block.read_f32()?, block.read_f32()?],
+            b"CMYK" => {
+                let [c, m, y, k] = [
+                    block.read_f32()?,
+                    block.read_f32()?,
+                    block.read_f32()?,
+                    block.read_f32()?,
+                ];
+                [
+                    (1.0 - c) * (1.0 - k),
+                    (1.0 - m) * (1.0 - k),
+                    (1.0 - y) * (1.0 - k),
+                ]
+            }
+            b"Gray" => {
+                let gray = block.read_f32()?;
+                [gray, gray, gray]
+            }
+            b"LAB " => lab_to_srgb([block.read_f32()?, block.read_f32()?, block.read_f32()?]),
+            _ => {
+                log::warn!(
+                    "Skipping a swatch with an unknown color model: {:?}",
+                    String::from_utf8_lossy(model)
+                );
+                continue;
+            }
+        };
+
+        colors.push(color.map(|channel| channel.clamp(0.0, 1.0)));
+    }
+
+    Ok(colors)
+}
+
+struct AseReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> AseReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Problem> {
+        if len > self.bytes.len() {
+            return Err(Problem::TruncatedAse);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn read_u16(&mut self) -> Result<u16, Problem> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Problem> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn read_f32(&mut self) -> Result<f32, Problem> {
+        Ok(f32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+// Convert a CIELAB color, with L in [0, 1], relative to D50, to sRGB.
+fn lab_to_srgb([l, a, b]: [f32; 3]) -> [f32; 3] {
+    const WHITE: [f32; 3] = [0.96422, 1.0, 0.82521];
+
+    let fy = (100.0 * l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let f_inv = |t: f32| {
+        if t > 6.0 / 29.0 {
+            t * t * t
+        } else {
+            3.0 * (6.0f32 / 29.0).powi(2) * (t - 4.0 / 29.0)
+        }
+    };
+    let [x, y, z] = [
+        WHITE[0] * f_inv(fx),
+        WHITE[1] * f_inv(fy),
+        WHITE[2] * f_inv(fz),
+    ];
+
+    // Bradford-adapted XYZ (D50) to linear sRGB.
+    let linear = [
+        3.133856 * x - 1.616867 * y - 0.4906146 * z,
+        -0.9787684 * x + 1.9161415 * y + 0.033454 * z,
+        0.0719453 * x - 0.2289914 * y + 1.4052427 * z,
+    ];
+
+    linear.map(|c| {
+        let c = c.clamp(0.0, 1.0);
+        if c <= 0.0031308 {
+            12.92 * c
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn parses_gimp_palettes() {
+        let gpl = "GIMP Palette\nName: Brand\nColumns: 2\n# A comment\n255   0   0\tRed\n  0 128 255 Sky blue\n\n";
+        assert_eq!(
+            parse_gpl(gpl).unwrap(),
+            [[1.0, 0.0, 0.0], [0.0, 128.0 / 255.0, 1.0]]
+        );
+
+        assert!(matches!(parse_gpl("255 0 0"), Err(Problem::NotGpl)));
+        assert!(matches!(
+            parse_gpl("GIMP Palette\n255 0\n"),
+            Err(Problem::InvalidGplColor { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn parses_hex_lists() {
+        let css =
+            ":root {\n  --primary: #FF0000;\n  --accent: #0f0;\n}\n#header { color: #0000ff80; }";
+        assert_eq!(
+            parse_hex_list(css),
+            [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+        );
+
+        assert_eq!(
+            parse_hex_list("ff0000\n00ff00\n"),
+            [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+    }
+
+    #[test]
+    fn parses_swatch_exchange_files() {
+        let mut ase = Vec::new();
+        ase.extend_from_slice(b"ASEF");
+        ase.extend_from_slice(&[0, 1, 0, 0]);
+        ase.extend_from_slice(&4u32.to_be_bytes());
+
+        let mut push_block = |block_type: u16, data: &[u8]| {
+            ase.extend_from_slice(&block_type.to_be_bytes());
+            ase.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            ase.extend_from_slice(data);
+        };
+        let color_entry = |model: &[u8], values: &[f32]| {
+            // A single-character name: "A\0".
+            let mut data = vec![0, 2, 0, b'A', 0, 0];
+            data.extend_from_slice(model);
+            for value in values {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            data.extend_from_slice(&[0, 2]);
+            data
+        };
+
+        push_block(0xC001, &[0, 1, 0, 0]);
+        push_block(0x0001, &color_entry(b"RGB ", &[1.0, 0.5, 0.0]));
+        push_block(0x0001, &color_entry(b"CMYK", &[0.0, 1.0, 1.0, 0.0]));
+        push_block(0x0001, &color_entry(b"LAB ", &[1.0, 0.0, 0.0]));
+
+        let colors = parse_ase(&ase).unwrap();
+        assert_eq!(colors.len(), 3);
+        assert_eq!(colors[0], [1.0, 0.5, 0.0]);
+        assert_eq!(colors[1], [1.0, 0.0, 0.0]);
+        for channel in colors[2] {
+            assert_relative_eq!(channel, 1.0, epsilon = 1e-3);
+        }
+
+        assert!(matches!(parse_ase(&ase[..20]), Err(Problem::TruncatedAse)));
+        assert!(matches!(parse_ase(b"GIMP"), Err(Problem::NotAse)));
+    }
+
+    #[test]
+    fn extracts_dominant_colors_ordered_by_hue() {
+        // Quadrants of blue, red, green, and a darker red.
+        let image = RgbaImage::from_fn(64, 64, |x, y| match (x < 32, y < 32) {
+            (true, true) => image::Rgba([0, 0, 255, 255]),
+            (false, true) => image::Rgba([255, 0, 0, 255]),
+            (true, false) => image::Rgba([0, 255, 0, 255]),
+            (false, false) => image::Rgba([200, 0, 0, 255]),
+        });
+
+        let palette = extract_from_image(&image, 3);
+        let colors = palette
+            .stops
+            .iter()
+            .map(|stop| stop.color)
+            .collect::<Vec<_>>();
+        assert_eq!(colors.len(), 3);
+        // Both reds end up in one box.
+        assert_relative_eq!(colors[0][0], 227.5 / 255.0, epsilon = 1e-3);
+        assert_eq!(colors[1], [0.0, 1.0, 0.0]);
+        assert_eq!(colors[2], [0.0, 0.0, 1.0]);
+
+        // Never more colors than there are in the image.
+        assert_eq!(extract_from_image(&image, 16).stops.len(), 4);
+    }
+
+    #[test]
+    fn rejects_empty_palettes() {
+        assert!(matches!(
+            parse(Format::Hex, b"nothing to see here"),
+            Err(Problem::NoColors)
+        ));
+        assert_eq!(Format::from_path(Path::new("brand.GPL")), Some(Format::Gpl));
+        assert_eq!(Format::from_path(Path::new("photo.png")), None);
+    }
+}
@@ -0,0 +1,309 @@
+// Rotate through looks on a schedule.
+//
+// A playlist is a list of entries, each either a full settings bundle or a
+// color preset applied on top of the base settings. The player keeps track of
+// time and hands out the settings for the current entry, which the host passes
+// on to `Flux::update`.
+
+use crate::rng;
+use crate::settings::{ColorMode, ColorPreset, Settings, ValidationError};
+
+use rand::seq::SliceRandom;
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Problem {
+    #[error("The playlist is empty")]
+    Empty,
+
+    #[error("Invalid playlist `{field}`: must be a positive number of seconds, got {duration}")]
+    InvalidDuration { field: String, duration: f32 },
+
+    #[error("Invalid settings in playlist entry {index}: {source}")]
+    InvalidSettings {
+        index: usize,
+        source: ValidationError,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Playlist {
+    pub entries: Vec<Entry>,
+    // How long to show entries without a duration of their own, in seconds.
+    pub duration: f32,
+    pub shuffle: bool,
+    // Start over after the last entry. Otherwise, stay on the last entry.
+    #[serde(rename = "loop")]
+    pub repeat: bool,
+    // Seed the shuffle.
+    pub seed: Option<String>,
+}
+
+impl Default for Playlist {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            duration: 60.0,
+            shuffle: false,
+            repeat: true,
+            seed: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    #[serde(flatten)]
+    pub look: Look,
+    // In seconds. Defaults to the playlist’s duration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f32>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Look {
+    // A color preset on top of the base settings.
+    Preset(ColorPreset),
+    Settings(Box<Settings>),
+}
+
+impl Playlist {
+    pub fn validate(&self) -> Result<(), Problem> {
+        if self.entries.is_empty() {
+            return Err(Problem::Empty);
+        }
+
+        check_duration("duration".to_string(), self.duration)?;
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            if let Some(duration) = entry.duration {
+                check_duration(format!("entries[{}].duration", index), duration)?;
+            }
+            if let Look::Settings(settings) = &entry.look {
+                settings
+                    .validate()
+                    .map_err(|source| Problem::InvalidSettings { index, source })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn check_duration(field: String, duration: f32) -> Result<(), Problem> {
+    if duration.is_finite() && duration > 0.0 {
+        Ok(())
+    } else {
+        Err(Problem::InvalidDuration { field, duration })
+    }
+}
+
+pub struct Player {
+    playlist: Playlist,
+    base_settings: Arc<Settings>,
+    settings: Arc<Settings>,
+
+    // The order in which to play the entries. Reshuffled on every loop.
+    order: Vec<usize>,
+    position: usize,
+    // When the current entry started, in milliseconds. Set on the first tick.
+    entry_start: Option<f64>,
+    rng: Pcg32,
+}
+
+impl Player {
+    pub fn new(playlist: Playlist, base_settings: Arc<Settings>) -> Result<Self, Problem> {
+        playlist.validate()?;
+
+        let mut rng = rng::from_seed(playlist.seed.as_deref());
+        let mut order = (0..playlist.entries.len()).collect::<Vec<_>>();
+        if playlist.shuffle {
+            order.shuffle(&mut rng);
+        }
+
+        let mut player = Self {
+            settings: Arc::clone(&base_settings),
+            playlist,
+            base_settings,
+            order,
+            position: 0,
+            entry_start: None,
+            rng,
+        };
+        player.settings = player.entry_settings();
+
+        Ok(player)
+    }
+
+    // The settings for the current entry.
+    pub fn settings(&self) -> &Arc<Settings> {
+        &self.settings
+    }
+
+    // The index of the current entry in the playlist.
+    pub fn current_entry(&self) -> usize {
+        self.order[self.position]
+    }
+
+    // Replace the settings that presets apply on top of. Returns the new
+    // settings for the current entry.
+    pub fn set_base_settings(&mut self, base_settings: Arc<Settings>) -> &Arc<Settings> {
+        self.base_settings = base_settings;
+        self.settings = self.entry_settings();
+        &self.settings
+    }
+
+    // Advance the playlist to `timestamp`, in milliseconds. Returns the new
+    // settings when it moves on to the next entry.
+    pub fn tick(&mut self, timestamp: f64) -> Option<&Arc<Settings>> {
+        let entry_start = *self.entry_start.get_or_insert(timestamp);
+        if timestamp - entry_start < 1000.0 * f64::from(self.entry_duration()) {
+            return None;
+        }
+
+        if !self.advance() {
+            return None;
+        }
+
+        self.entry_start = Some(timestamp);
+        self.settings = self.entry_settings();
+        Some(&self.settings)
+    }
+
+    fn advance(&mut self) -> bool {
+        if self.position + 1 < self.order.len() {
+            self.position += 1;
+            return true;
+        }
+
+        if !self.playlist.repeat {
+            return false;
+        }
+
+        if self.playlist.shuffle && self.order.len() > 1 {
+            // Avoid playing the same entry twice in a row across loops.
+            let last = self.order[self.position];
+            self.order.shuffle(&mut self.rng);
+            if self.order[0] == last {
+                let swap_with = self.order.len() - 1;
+                self.order.swap(0, swap_with);
+            }
+        }
+
+        self.position = 0;
+        true
+    }
+
+    fn entry_duration(&self) -> f32 {
+        self.playlist.entries[self.current_entry()]
+            .duration
+            .unwrap_or(self.playlist.duration)
+    }
+
+    fn entry_settings(&self) -> Arc<Settings> {
+        match &self.playlist.entries[self.current_entry()].look {
+            Look::Preset(preset) => Arc::new(Settings {
+                color_mode: ColorMode::Preset(*preset),
+                ..(*self.base_settings).clone()
+            }),
+            Look::Settings(settings) => Arc::new((**settings).clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn presets(presets: &[ColorPreset]) -> Playlist {
+        Playlist {
+            entries: presets
+                .iter()
+                .map(|preset| Entry {
+                    look: Look::Preset(*preset),
+                    duration: None,
+                })
+                .collect(),
+            duration: 1.0,
+            ..Default::default()
+        }
+    }
+
+    fn play(player: &mut Player, seconds: usize) -> Vec<usize> {
+        (0..=seconds)
+            .map(|second| {
+                player.tick(1000.0 * second as f64);
+                player.current_entry()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plays_entries_in_order() {
+        let playlist = presets(&[ColorPreset::Plasma, ColorPreset::Poolside]);
+        let mut player = Player::new(playlist, Arc::default()).unwrap();
+        assert_eq!(
+            player.settings().color_mode,
+            ColorMode::Preset(ColorPreset::Plasma)
+        );
+        assert_eq!(play(&mut player, 4), vec![0, 1, 0, 1, 0]);
+
+        let playlist = Playlist {
+            repeat: false,
+            ..presets(&[ColorPreset::Plasma, ColorPreset::Poolside])
+        };
+        let mut player = Player::new(playlist, Arc::default()).unwrap();
+        assert_eq!(play(&mut player, 4), vec![0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn shuffles_every_entry_once_per_loop() {
+        let playlist = Playlist {
+            shuffle: true,
+            seed: Some("playlist".to_string()),
+            ..presets(&[
+                ColorPreset::Original,
+                ColorPreset::Plasma,
+                ColorPreset::Poolside,
+                ColorPreset::Freedom,
+            ])
+        };
+        let mut player = Player::new(playlist, Arc::default()).unwrap();
+        let entries = play(&mut player, 11);
+
+        for round in entries.chunks(4) {
+            let mut round = round.to_vec();
+            round.sort();
+            assert_eq!(round, vec![0, 1, 2, 3]);
+        }
+        assert!(entries.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn uses_per_entry_durations() {
+        let mut playlist = presets(&[ColorPreset::Plasma, ColorPreset::Poolside]);
+        playlist.entries[0].duration = Some(3.0);
+        let mut player = Player::new(playlist, Arc::default()).unwrap();
+        assert_eq!(play(&mut player, 5), vec![0, 0, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn rejects_invalid_playlists() {
+        assert!(matches!(
+            Player::new(Playlist::default(), Arc::default()),
+            Err(Problem::Empty)
+        ));
+
+        let mut playlist = presets(&[ColorPreset::Plasma]);
+        playlist.entries[0].duration = Some(0.0);
+        assert!(matches!(
+            playlist.validate(),
+            Err(Problem::InvalidDuration { .. })
+        ));
+    }
+}
@@ -1,19 +1,19 @@
 use image::{DynamicImage, GenericImage, GenericImageView, Rgba, RgbaImage};
 
+#[derive(Debug, thiserror::Error)]
 pub enum Problem {
-    ReadImage(std::io::Error),
-    DecodeColorTexture(image::ImageError),
-}
-
-impl std::fmt::Display for Problem {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Problem::ReadImage(err) => write!(f, "Failed to read image: {}", err),
-            Problem::DecodeColorTexture(err) => {
-                write!(f, "Failed to decode color texture: {}", err)
-            }
-        }
-    }
+    #[error("Failed to read image: {0}")]
+    ReadImage(#[from] std::io::Error),
+
+    #[error("Failed to decode color texture: {0}")]
+    DecodeColorTexture(#[from] image::ImageError),
+
+    #[error("Can’t use a {width}x{height} image as a color texture (max size: {max_size})")]
+    InvalidImageSize {
+        width: u32,
+        height: u32,
+        max_size: u32,
+    },
 }
 
 pub struct Context {
@@ -23,15 +23,14 @@ pub struct Context {
 }
 
 impl Context {
-    // pub fn load_color_texture_from_file(device: &wgpu::Device, queue: &wgpu::Queue, path: &path::PathBuf) -> Result<Context, Problem> {
-    //     std::fs::read(path)
-    //         .map_err(Problem::ReadImage)
-    //         .and_then(|ref encoded_bytes| Self::load_color_texture(device, queue, encoded_bytes))
-    //         .map_err(|err| {
-    //             log::error!("Failed to load image from {}: {}", path.display(), err);
-    //             err
-    //         })
-    // }
+    pub fn read_color_texture(path: &std::path::Path) -> Result<RgbaImage, Problem> {
+        std::fs::read(path)
+            .map_err(Problem::ReadImage)
+            .and_then(|ref encoded_bytes| Self::decode_color_texture(encoded_bytes))
+            .inspect_err(|err| {
+                log::error!("Failed to load image from {}: {}", path.display(), err);
+            })
+    }
 
     pub fn decode_color_texture(encoded_bytes: &[u8]) -> Result<RgbaImage, Problem> {
         log::debug!("Decoding image");
@@ -82,9 +81,18 @@ pub fn load_color_texture(
     device: &wgpu::Device,
     queue: &wgpu::Queue,
     img: &RgbaImage,
-) -> wgpu::TextureView {
+) -> Result<wgpu::TextureView, Problem> {
     let width = img.width();
     let height = img.height();
+    let max_size = device.limits().max_texture_dimension_2d;
+    if width == 0 || height == 0 || width > max_size || height > max_size {
+        return Err(Problem::InvalidImageSize {
+            width,
+            height,
+            max_size,
+        });
+    }
+
     let size = wgpu::Extent3d {
         width,
         height,
@@ -119,5 +127,23 @@ pub fn load_color_texture(
         size,
     );
 
-    texture.create_view(&wgpu::TextureViewDescriptor::default())
+    Ok(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_unreadable_image_files() {
+        let missing = std::env::temp_dir().join("flux-missing-color-image.png");
+        assert!(matches!(
+            Context::read_color_texture(&missing),
+            Err(Problem::ReadImage(_))
+        ));
+        assert!(matches!(
+            Context::decode_color_texture(b"not an image"),
+            Err(Problem::DecodeColorTexture(_))
+        ));
+    }
 }
@@ -1,9 +1,17 @@
+use crate::audio;
 use crate::grid::Grid;
+use crate::palette;
+use crate::render::color;
+use crate::render::readback;
 use crate::render::view::ViewTransform;
-use crate::settings::{ColorMode, Settings};
+use crate::settings::{
+    ColorMode, ColorTransition, ImageColorMode, Interpolation, Palette, Settings,
+};
+use crate::snapshot::{self, ColorTransitionState, LineState};
 
 use bytemuck::Zeroable;
 use std::borrow::Cow;
+use std::path::PathBuf;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
@@ -27,8 +35,19 @@ struct LineUniforms {
     color_mode: u32, // 44
 
     delta_time: f32, // 48
-    _padding: u32,   // 52
-                     // roundUp(52, 8) = 56
+
+    // How to mix the colors of a palette
+    // 0 => sRGB
+    // 1 => Linear RGB
+    // 2 => OKLab
+    color_interpolation: u32, // 52
+
+    // The colors to fade from after the colors change
+    previous_color_mode: u32,          // 56
+    previous_color_interpolation: u32, // 60
+    // 0 => previous colors, 1 => current colors
+    color_blend: f32, // 64
+    _padding: u32,    // 68
 }
 
 impl LineUniforms {
@@ -50,32 +69,57 @@ impl LineUniforms {
             line_noise_blend_factor: 0.0,
             color_mode: settings.color_mode.clone().into(),
             delta_time: 1.0 / 60.0, // Initial value, will be updated every frame
+            color_interpolation: match &settings.color_mode {
+                ColorMode::Palette(palette) => palette.interpolation.into(),
+                _ => Interpolation::Srgb.into(),
+            },
+            previous_color_mode: 0,
+            previous_color_interpolation: 0,
+            color_blend: 1.0,
             _padding: 0,
         }
     }
 
     fn tick(&mut self, timestep: f32, elapsed_time: f32) -> &mut Self {
-        const BLEND_THRESHOLD: f32 = 4.0;
-        const BASE_OFFSET: f32 = 0.0015;
+        let mut line_noise = [
+            self.line_noise_offset_1,
+            self.line_noise_offset_2,
+            self.line_noise_blend_factor,
+        ];
+        tick_line_noise(&mut line_noise, elapsed_time);
+        [
+            self.line_noise_offset_1,
+            self.line_noise_offset_2,
+            self.line_noise_blend_factor,
+        ] = line_noise;
 
-        let perturb = 1.0 + 0.2 * (0.010 * elapsed_time * std::f32::consts::TAU).sin();
-        let offset = BASE_OFFSET * perturb;
-        self.line_noise_offset_1 += offset;
+        self.delta_time = timestep;
 
-        if self.line_noise_offset_1 > BLEND_THRESHOLD {
-            self.line_noise_offset_2 += offset;
-            self.line_noise_blend_factor += BASE_OFFSET;
-        }
+        self
+    }
+}
 
-        if self.line_noise_blend_factor > 1.0 {
-            self.line_noise_offset_1 = self.line_noise_offset_2;
-            self.line_noise_offset_2 = 0.0;
-            self.line_noise_blend_factor = 0.0;
-        }
+// Drift the line noise: [offset_1, offset_2, blend_factor]. Once the first
+// offset passes a threshold, blend over to a second offset, starting from zero.
+pub(crate) fn tick_line_noise(line_noise: &mut [f32; 3], elapsed_time: f32) {
+    const BLEND_THRESHOLD: f32 = 4.0;
+    const BASE_OFFSET: f32 = 0.0015;
 
-        self.delta_time = timestep;
+    let [offset_1, offset_2, blend_factor] = line_noise;
 
-        self
+    let perturb = 1.0 + 0.2 * (0.010 * elapsed_time * std::f32::consts::TAU).sin();
+    let offset = BASE_OFFSET * perturb;
+    *offset_1 += offset;
+
+    if *offset_1 > BLEND_THRESHOLD {
+        *offset_2 += offset;
+        *blend_factor += BASE_OFFSET;
+    }
+
+    if *blend_factor > 1.0 {
+        *offset_1 = *offset_2;
+        *offset_2 = 0.0;
+        *blend_factor = 0.0;
     }
 }
 
@@ -109,8 +153,30 @@ struct Line {
     width: f32,
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ResampleUniforms {
+    // The columns and rows of the previous grid
+    grid_size: [u32; 2],
+    line_count: u32,
+    _padding: u32,
+}
+
+// Colors loaded from an image, ready to bind
+pub enum ImageColors {
+    Texture(wgpu::TextureView),
+    Palette(Palette),
+}
+
+// An image loaded by `Context::load_color_image`
+pub struct ColorImage {
+    source: (PathBuf, ImageColorMode),
+    colors: ImageColors,
+}
+
 pub struct Context {
     line_count: u32,
+    grid_size: [u32; 2],
     work_group_count: u32,
     frame_num: usize,
 
@@ -123,6 +189,8 @@ pub struct Context {
     line_buffers: Vec<wgpu::Buffer>,
 
     linear_sampler: wgpu::Sampler,
+    // The fluid’s obstacle mask, which hides the lines inside obstacles
+    obstacle_texture_view: wgpu::TextureView,
     uniform_bind_group_layout: wgpu::BindGroupLayout,
     uniform_bind_group: wgpu::BindGroup,
     view_uniform_bind_group_layout: wgpu::BindGroupLayout,
@@ -134,10 +202,23 @@ pub struct Context {
     color_texture_sampler: wgpu::Sampler,
     color_texture_view: wgpu::TextureView,
     color_buffer: wgpu::Buffer,
+    // The stops in the color buffer, if they came from a palette.
+    color_stops: Vec<[f32; 4]>,
+    previous_color_texture_view: wgpu::TextureView,
+    previous_color_buffer: wgpu::Buffer,
+    // The stops in the previous color buffer, for snapshots
+    previous_color_stops: Vec<[f32; 4]>,
+    color_transition: ColorTransition,
+    color_transition_time: f32,
+    modulation: audio::Modulation,
     color_bind_group_layout: wgpu::BindGroupLayout,
     color_bind_group: wgpu::BindGroup,
+    // The image file the current colors come from, if any.
+    color_image_source: Option<(PathBuf, ImageColorMode)>,
 
     place_lines_pipeline: wgpu::ComputePipeline,
+    resample_bind_group_layout: wgpu::BindGroupLayout,
+    resample_lines_pipeline: wgpu::ComputePipeline,
     draw_line_pipeline: wgpu::RenderPipeline,
     draw_endpoint_pipeline: wgpu::RenderPipeline,
 }
@@ -150,30 +231,50 @@ impl Context {
         screen_size: wgpu::Extent3d,
         grid: &Grid,
         settings: &Settings,
+        color_image: Option<ColorImage>,
     ) {
+        self.color_transition = settings.color_transition;
+
+        if let Some(ColorImage { source, colors }) = color_image {
+            self.set_image_colors(device, queue, colors);
+            self.color_image_source = Some(source);
+        }
+
         self.line_uniforms = {
             let mut new_line_uniforms = LineUniforms::new(screen_size, grid, settings);
             new_line_uniforms.line_noise_offset_1 = self.line_uniforms.line_noise_offset_1;
             new_line_uniforms.line_noise_offset_2 = self.line_uniforms.line_noise_offset_2;
             new_line_uniforms.line_noise_blend_factor = self.line_uniforms.line_noise_blend_factor;
+            // The color bindings decide these.
+            new_line_uniforms.color_mode = self.color_mode;
+            new_line_uniforms.color_interpolation = self.line_uniforms.color_interpolation;
+            new_line_uniforms.previous_color_mode = self.line_uniforms.previous_color_mode;
+            new_line_uniforms.previous_color_interpolation =
+                self.line_uniforms.previous_color_interpolation;
+            new_line_uniforms.color_blend = self.line_uniforms.color_blend;
 
             new_line_uniforms
         };
 
-        if let ColorMode::Preset(preset) = settings.color_mode {
-            if let Some(color_wheel) = preset.to_color_wheel() {
-                self.color_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-                    label: Some("buffer:color"),
-                    size: 4 * (color_wheel.len() as u64),
-                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
-                    mapped_at_creation: false,
-                });
-
-                queue.write_buffer(&self.color_buffer, 0, bytemuck::cast_slice(&[color_wheel]));
-
-                self.color_mode = 1;
-                self.update_color_bindings(device, queue, None, None);
-            }
+        match &settings.color_mode {
+            ColorMode::Preset(preset) => match preset.to_palette() {
+                Some(palette) => self.set_palette(device, queue, &palette),
+                None => {
+                    if self.color_mode != 0 {
+                        self.begin_color_transition();
+                        // Switching back to an image should load it again.
+                        self.color_stops.clear();
+                        self.color_image_source = None;
+                        self.update_color_bind_group(device);
+                    }
+                    self.color_mode = 0;
+                    self.line_uniforms.color_mode = 0;
+                }
+            },
+            ColorMode::Palette(palette) => self.set_palette(device, queue, palette),
+            // Either loaded by `load_color_image`, or provided by the host on
+            // the web.
+            ColorMode::ImageFile(_) => (),
         }
 
         queue.write_buffer(
@@ -183,6 +284,38 @@ impl Context {
         );
     }
 
+    // Load the image in the settings, if it’s new, for the next `update`.
+    //
+    // This doesn’t touch the current colors, so that a missing or broken file
+    // leaves the lines as they are.
+    //
+    // On the web, the path is a URL. The host fetches the image and passes it
+    // to `Flux::sample_colors_from_texture_view` instead.
+    pub fn load_color_image(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &Settings,
+    ) -> Result<Option<ColorImage>, color::Problem> {
+        match &settings.color_mode {
+            #[cfg(not(target_arch = "wasm32"))]
+            ColorMode::ImageFile(path) => {
+                let source = (path.clone(), settings.image_color_mode);
+                if self.color_image_source.as_ref() == Some(&source) {
+                    return Ok(None);
+                }
+
+                let image = color::Context::read_color_texture(path)?;
+                let colors = load_image_colors(device, queue, &image, settings.image_color_mode)?;
+                Ok(Some(ColorImage { source, colors }))
+            }
+            _ => {
+                let _ = (device, queue);
+                Ok(None)
+            }
+        }
+    }
+
     pub fn set_view_transform(&self, queue: &wgpu::Queue, view_transform: ViewTransform) {
         let view_matrix = ViewUniform::from(view_transform);
         queue.write_buffer(
@@ -192,6 +325,60 @@ impl Context {
         );
     }
 
+    // Color the lines with an image, either by sampling it directly, or by
+    // extracting a palette for the color wheel.
+    pub fn set_color_image(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &image::RgbaImage,
+        image_color_mode: ImageColorMode,
+    ) -> Result<(), color::Problem> {
+        let colors = load_image_colors(device, queue, image, image_color_mode)?;
+        self.set_image_colors(device, queue, colors);
+        Ok(())
+    }
+
+    fn set_image_colors(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        colors: ImageColors,
+    ) {
+        match colors {
+            ImageColors::Texture(texture_view) => {
+                self.update_color_bindings(device, queue, Some(texture_view), None);
+            }
+            ImageColors::Palette(palette) => self.set_palette(device, queue, &palette),
+        }
+    }
+
+    // Use a palette for the color wheel.
+    pub fn set_palette(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, palette: &Palette) {
+        let color_stops = palette.to_color_stops();
+        let color_interpolation = palette.interpolation.into();
+
+        // Settings updates pass the same palette again. Don’t restart the
+        // transition for those.
+        if self.color_mode == 1
+            && self.color_stops == color_stops
+            && self.line_uniforms.color_interpolation == color_interpolation
+        {
+            return;
+        }
+
+        let color_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("buffer:color"),
+            contents: bytemuck::cast_slice(&color_stops),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+        });
+
+        self.begin_color_transition();
+        self.line_uniforms.color_interpolation = color_interpolation;
+        self.bind_colors(device, queue, None, Some(color_buffer));
+        self.color_stops = color_stops;
+    }
+
     pub fn update_color_bindings(
         &mut self,
         device: &wgpu::Device,
@@ -199,37 +386,67 @@ impl Context {
         some_color_texture_view: Option<wgpu::TextureView>,
         some_color_buffer: Option<wgpu::Buffer>,
     ) {
+        self.begin_color_transition();
+        self.bind_colors(device, queue, some_color_texture_view, some_color_buffer);
+    }
+
+    // Fade from the current colors to whatever gets bound next.
+    //
+    // If a transition is already running, it restarts from the newer colors.
+    fn begin_color_transition(&mut self) {
+        self.previous_color_texture_view = self.color_texture_view.clone();
+        self.previous_color_buffer = self.color_buffer.clone();
+        self.previous_color_stops = self.color_stops.clone();
+        self.line_uniforms.previous_color_mode = self.color_mode;
+        self.line_uniforms.previous_color_interpolation = self.line_uniforms.color_interpolation;
+
+        self.color_transition_time = 0.0;
+        self.line_uniforms.color_blend = self.color_transition.progress(0.0);
+    }
+
+    fn finish_color_transition(&mut self, queue: &wgpu::Queue) {
+        self.line_uniforms.color_blend = 1.0;
+
+        queue.write_buffer(
+            &self.line_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.line_uniforms]),
+        );
+    }
+
+    fn bind_colors(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        some_color_texture_view: Option<wgpu::TextureView>,
+        some_color_buffer: Option<wgpu::Buffer>,
+    ) {
+        self.color_stops.clear();
         if let Some(color_texture_view) = some_color_texture_view {
             self.color_texture_view = color_texture_view;
             self.color_mode = 2;
+            self.color_image_source = None;
         }
         if let Some(color_buffer) = some_color_buffer {
             self.color_buffer = color_buffer;
             self.color_mode = 1;
+            self.color_image_source = None;
         }
 
-        self.color_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:color"),
-            layout: &self.color_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&self.color_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &self.color_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-            ],
-        });
-
+        self.update_color_bind_group(device);
         self.update_line_color_mode(device, queue);
     }
 
+    // Bind the current and previous colors.
+    fn update_color_bind_group(&mut self, device: &wgpu::Device) {
+        self.color_bind_group = create_color_bind_group(
+            device,
+            &self.color_bind_group_layout,
+            [&self.color_texture_view, &self.previous_color_texture_view],
+            [&self.color_buffer, &self.previous_color_buffer],
+        );
+    }
+
     pub fn tick_line_uniforms(
         &mut self,
         _device: &wgpu::Device,
@@ -239,13 +456,32 @@ impl Context {
     ) {
         self.line_uniforms.tick(timestep, elapsed_time);
 
+        if self.line_uniforms.color_blend < 1.0 {
+            self.color_transition_time += timestep * self.modulation.color_transition_speed;
+            self.line_uniforms.color_blend =
+                self.color_transition.progress(self.color_transition_time);
+        }
+
+        // This runs before every draw, so the modulated width always makes it
+        // to the GPU.
+        let line_uniforms = LineUniforms {
+            line_width: self.line_uniforms.line_width * self.modulation.line_width,
+            ..self.line_uniforms
+        };
+
         queue.write_buffer(
             &self.line_uniform_buffer,
             0,
-            bytemuck::cast_slice(&[self.line_uniforms]),
+            bytemuck::cast_slice(&[line_uniforms]),
         );
     }
 
+    // Scale the line width and the speed of color transitions, for
+    // audio-reactive lines.
+    pub fn set_modulation(&mut self, modulation: &audio::Modulation) {
+        self.modulation.clone_from(modulation);
+    }
+
     pub fn update_line_color_mode(&mut self, _device: &wgpu::Device, queue: &wgpu::Queue) {
         self.line_uniforms.color_mode = self.color_mode;
 
@@ -256,7 +492,6 @@ impl Context {
         );
     }
 
-    // TODO: resample the line state in a compute shader
     // TODO: dedupe with new
     pub fn resize(
         &mut self,
@@ -266,8 +501,13 @@ impl Context {
         grid: &Grid,
         settings: &Settings,
     ) {
-        self.update(device, queue, screen_size, grid, settings);
+        self.update(device, queue, screen_size, grid, settings, None);
 
+        self.set_grid(device, queue, grid);
+    }
+
+    // Move the lines onto a new grid, resampling the current line state.
+    pub fn set_grid(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, grid: &Grid) {
         let basepoints_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("buffer:basepoints"),
             contents: bytemuck::cast_slice(&grid.basepoints),
@@ -285,6 +525,7 @@ impl Context {
                     contents: bytemuck::cast_slice(&lines),
                     usage: wgpu::BufferUsages::VERTEX
                         | wgpu::BufferUsages::STORAGE
+                        | wgpu::BufferUsages::COPY_SRC
                         | wgpu::BufferUsages::COPY_DST,
                 })
             })
@@ -311,42 +552,113 @@ impl Context {
             })
             .collect::<Vec<_>>();
 
-        self.uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:uniforms"),
-            layout: &self.uniform_bind_group_layout,
+        self.resample_lines(device, queue, grid, &basepoints_buffer, &line_buffers[0]);
+
+        self.uniform_bind_group = self.create_uniform_bind_group(device, &basepoints_buffer);
+
+        self.line_count = grid.line_count;
+        self.grid_size = [grid.columns, grid.rows];
+        self.work_group_count = ((grid.line_count as f32) / 64.0).ceil() as u32;
+        self.frame_num = 0;
+        self.line_buffers = line_buffers;
+        self.line_bind_groups = line_bind_groups;
+        self.basepoints_buffer = basepoints_buffer;
+    }
+
+    // Hide the lines inside the obstacles of a mask that covers the lines.
+    pub fn set_obstacle_texture_view(
+        &mut self,
+        device: &wgpu::Device,
+        obstacle_texture_view: &wgpu::TextureView,
+    ) {
+        self.obstacle_texture_view = obstacle_texture_view.clone();
+        self.uniform_bind_group = self.create_uniform_bind_group(device, &self.basepoints_buffer);
+    }
+
+    fn create_uniform_bind_group(
+        &self,
+        device: &wgpu::Device,
+        basepoints_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        create_uniform_bind_group(
+            device,
+            &self.uniform_bind_group_layout,
+            &self.line_uniform_buffer,
+            basepoints_buffer,
+            [&self.linear_sampler, &self.color_texture_sampler],
+            &self.obstacle_texture_view,
+        )
+    }
+
+    // Interpolate the current lines onto the basepoints of the new grid.
+    fn resample_lines(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        grid: &Grid,
+        basepoints_buffer: &wgpu::Buffer,
+        out_line_buffer: &wgpu::Buffer,
+    ) {
+        let resample_uniforms = ResampleUniforms {
+            grid_size: self.grid_size,
+            line_count: grid.line_count,
+            _padding: 0,
+        };
+        let resample_uniform_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("buffer:ResampleUniforms"),
+                contents: bytemuck::cast_slice(&[resample_uniforms]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+
+        let resample_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:resample_lines"),
+            layout: &self.resample_bind_group_layout,
             entries: &[
                 // uniforms
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &self.line_uniform_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
+                    resource: resample_uniform_buffer.as_entire_binding(),
                 },
                 // basepoints
                 wgpu::BindGroupEntry {
                     binding: 1,
                     resource: basepoints_buffer.as_entire_binding(),
                 },
-                // linear_sampler
+            ],
+        });
+
+        let line_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:lines"),
+            layout: &self.lines_bind_group_layout,
+            entries: &[
+                // lines
                 wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&self.linear_sampler),
+                    binding: 0,
+                    resource: self.line_buffers[self.frame_num].as_entire_binding(),
                 },
-                // color_texture_sampler
+                // out_lines
                 wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::Sampler(&self.color_texture_sampler),
+                    binding: 1,
+                    resource: out_line_buffer.as_entire_binding(),
                 },
             ],
         });
 
-        self.line_count = grid.line_count;
-        self.work_group_count = ((grid.line_count as f32) / 64.0).ceil() as u32;
-        self.line_buffers = line_buffers;
-        self.line_bind_groups = line_bind_groups;
-        self.basepoints_buffer = basepoints_buffer;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("flux:resample_lines"),
+        });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("flux::resample_lines"),
+                timestamp_writes: None,
+            });
+            cpass.set_pipeline(&self.resample_lines_pipeline);
+            cpass.set_bind_group(0, &resample_bind_group, &[]);
+            cpass.set_bind_group(1, &line_bind_group, &[]);
+            cpass.dispatch_workgroups(grid.line_count.div_ceil(64), 1, 1);
+        }
+        queue.submit(Some(encoder.finish()));
     }
 
     pub fn new(
@@ -356,7 +668,7 @@ impl Context {
         screen_size: wgpu::Extent3d,
         grid: &Grid,
         settings: &Settings,
-    ) -> Self {
+    ) -> Result<Self, color::Problem> {
         let line_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("buffer:vertices"),
             contents: bytemuck::cast_slice(&LINE_VERTICES),
@@ -400,6 +712,7 @@ impl Context {
                     contents: bytemuck::cast_slice(&lines),
                     usage: wgpu::BufferUsages::VERTEX
                         | wgpu::BufferUsages::STORAGE
+                        | wgpu::BufferUsages::COPY_SRC
                         | wgpu::BufferUsages::COPY_DST,
                 })
             })
@@ -465,39 +778,46 @@ impl Context {
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    // obstacle_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
                 ],
             });
 
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:uniforms"),
-            layout: &uniform_bind_group_layout,
-            entries: &[
-                // uniforms
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &line_uniform_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-                // basepoints
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: basepoints_buffer.as_entire_binding(),
-                },
-                // linear_sampler
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&linear_sampler),
-                },
-                // color_texture_sampler
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::Sampler(&color_texture_sampler),
-                },
-            ],
+        // No obstacles until the fluid provides a mask
+        let obstacle_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("texture:obstacle"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            view_formats: &[],
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
         });
+        let obstacle_texture_view =
+            obstacle_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let uniform_bind_group = create_uniform_bind_group(
+            device,
+            &uniform_bind_group_layout,
+            &line_uniform_buffer,
+            &basepoints_buffer,
+            [&linear_sampler, &color_texture_sampler],
+            &obstacle_texture_view,
+        );
 
         let view_uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -628,26 +948,36 @@ impl Context {
                         },
                         count: None,
                     },
+                    // previous_color_texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // previous_color_buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
                 ],
             });
-        let color_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &color_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&color_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &color_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-            ],
-        });
+        let color_bind_group = create_color_bind_group(
+            device,
+            &color_bind_group_layout,
+            [&color_texture_view, &color_texture_view],
+            [&color_buffer, &color_buffer],
+        );
 
         // TODO: reuse layout from fluid
         let velocity_bind_group_layout =
@@ -708,6 +1038,59 @@ impl Context {
                 cache: None,
             });
 
+        let resample_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:resample_lines"),
+                entries: &[
+                    // uniforms
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // basepoints
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let resample_lines_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("pipeline_layout:resample_lines"),
+                bind_group_layouts: &[&resample_bind_group_layout, &lines_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let resample_lines_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader:resample_lines"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "../../shader/resample_lines.comp.wgsl"
+            ))),
+        });
+
+        let resample_lines_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("pipeline:resample_lines"),
+                layout: Some(&resample_lines_pipeline_layout),
+                module: &resample_lines_shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
         let draw_line_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("pipeline_layout:draw_line"),
@@ -820,6 +1203,7 @@ impl Context {
 
         let mut lines = Self {
             line_count: grid.line_count,
+            grid_size: [grid.columns, grid.rows],
             work_group_count,
             frame_num: 0,
 
@@ -833,6 +1217,7 @@ impl Context {
 
             linear_sampler,
             color_texture_sampler,
+            obstacle_texture_view,
             uniform_bind_group_layout,
             uniform_bind_group,
             view_uniform_bind_group_layout,
@@ -841,20 +1226,117 @@ impl Context {
             line_bind_groups,
 
             color_mode: line_uniforms.color_mode,
+            previous_color_texture_view: color_texture_view.clone(),
+            previous_color_buffer: color_buffer.clone(),
+            previous_color_stops: Vec::new(),
             color_texture_view,
             color_buffer,
+            color_stops: Vec::new(),
+            color_transition: settings.color_transition,
+            color_transition_time: 0.0,
+            modulation: audio::Modulation::default(),
             color_bind_group_layout,
             color_bind_group,
+            color_image_source: None,
 
             place_lines_pipeline,
+            resample_bind_group_layout,
+            resample_lines_pipeline,
             draw_line_pipeline,
             draw_endpoint_pipeline,
         };
 
         // TODO: optimize this away
-        lines.update(device, queue, screen_size, grid, settings);
+        let color_image = lines.load_color_image(device, queue, settings)?;
+        lines.update(device, queue, screen_size, grid, settings, color_image);
+        // Start with the configured colors instead of fading in from the
+        // placeholders.
+        lines.finish_color_transition(queue);
+
+        Ok(lines)
+    }
 
-        lines
+    // Read back both line buffers and the line noise.
+    pub(crate) fn snapshot(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> LineState {
+        LineState {
+            line_count: self.line_count,
+            frame_num: self.frame_num as u32,
+            line_buffers: [
+                readback::read_buffer(device, queue, &self.line_buffers[0]),
+                readback::read_buffer(device, queue, &self.line_buffers[1]),
+            ],
+            noise_offsets: [
+                self.line_uniforms.line_noise_offset_1,
+                self.line_uniforms.line_noise_offset_2,
+                self.line_uniforms.line_noise_blend_factor,
+            ],
+            color_transition: ColorTransitionState {
+                time: self.color_transition_time,
+                blend: self.line_uniforms.color_blend,
+                previous_color_mode: self.line_uniforms.previous_color_mode,
+                previous_color_interpolation: self.line_uniforms.previous_color_interpolation,
+                previous_color_stops: self.previous_color_stops.clone(),
+            },
+        }
+    }
+
+    pub(crate) fn restore(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        state: &LineState,
+    ) -> Result<(), snapshot::Problem> {
+        snapshot::check("line count", state.line_count, self.line_count)?;
+        for line_buffer in &state.line_buffers {
+            snapshot::check(
+                "line data size",
+                line_buffer.len(),
+                self.line_count as usize * std::mem::size_of::<Line>(),
+            )?;
+        }
+
+        for (buffer, line_buffer) in self.line_buffers.iter().zip(&state.line_buffers) {
+            queue.write_buffer(buffer, 0, line_buffer);
+        }
+        self.frame_num = state.frame_num as usize % 2;
+
+        [
+            self.line_uniforms.line_noise_offset_1,
+            self.line_uniforms.line_noise_offset_2,
+            self.line_uniforms.line_noise_blend_factor,
+        ] = state.noise_offsets;
+        self.restore_color_transition(device, &state.color_transition);
+        queue.write_buffer(
+            &self.line_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[self.line_uniforms]),
+        );
+
+        Ok(())
+    }
+
+    // Resume a fade from the snapshot’s previous colors. Only palettes are
+    // stored, so a fade from an image skips to the end.
+    fn restore_color_transition(&mut self, device: &wgpu::Device, state: &ColorTransitionState) {
+        self.color_transition_time = state.time;
+        self.line_uniforms.color_blend = state.blend;
+        self.line_uniforms.previous_color_mode = state.previous_color_mode;
+        self.line_uniforms.previous_color_interpolation = state.previous_color_interpolation;
+
+        match state.previous_color_mode {
+            1 if !state.previous_color_stops.is_empty() => {
+                self.previous_color_buffer =
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("buffer:color"),
+                        contents: bytemuck::cast_slice(&state.previous_color_stops),
+                        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+                    });
+                self.previous_color_stops = state.previous_color_stops.clone();
+                self.update_color_bind_group(device);
+            }
+            2 => self.line_uniforms.color_blend = 1.0,
+            _ => (),
+        }
     }
 
     pub fn place_lines<'cpass>(
@@ -893,7 +1375,103 @@ impl Context {
     }
 }
 
-fn get_line_scale_factor(width: f32, height: f32) -> f32 {
+fn load_image_colors(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &image::RgbaImage,
+    image_color_mode: ImageColorMode,
+) -> Result<ImageColors, color::Problem> {
+    match image_color_mode {
+        ImageColorMode::Texture => {
+            let texture_view = color::load_color_texture(device, queue, image)?;
+            Ok(ImageColors::Texture(texture_view))
+        }
+        ImageColorMode::Palette { size } => Ok(ImageColors::Palette(palette::extract_from_image(
+            image,
+            size as usize,
+        ))),
+    }
+}
+
+// The uniforms and basepoints, the linear and color texture samplers, and the
+// obstacle mask
+fn create_uniform_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    line_uniform_buffer: &wgpu::Buffer,
+    basepoints_buffer: &wgpu::Buffer,
+    [linear_sampler, color_texture_sampler]: [&wgpu::Sampler; 2],
+    obstacle_texture_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("bind_group:uniforms"),
+        layout,
+        entries: &[
+            // uniforms
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: line_uniform_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+            // basepoints
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: basepoints_buffer.as_entire_binding(),
+            },
+            // linear_sampler
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(linear_sampler),
+            },
+            // color_texture_sampler
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::Sampler(color_texture_sampler),
+            },
+            // obstacle_texture
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(obstacle_texture_view),
+            },
+        ],
+    })
+}
+
+// The current and previous color sources, in that order.
+fn create_color_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    texture_views: [&wgpu::TextureView; 2],
+    buffers: [&wgpu::Buffer; 2],
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("bind_group:color"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(texture_views[0]),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: buffers[0].as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(texture_views[1]),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: buffers[1].as_entire_binding(),
+            },
+        ],
+    })
+}
+
+pub(crate) fn get_line_scale_factor(width: f32, height: f32) -> f32 {
     let aspect_ratio = width / height;
     let p = 1.0 / aspect_ratio;
     1.0 / ((1.0 - p) * width + p * height).min(2000.0)
@@ -918,3 +1496,95 @@ pub static ENDPOINT_VERTICES: [f32; 12] = [
     -1.0,  1.0,
      1.0,  1.0,
 ];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::headless::test_device;
+    use crate::Headless;
+    use std::sync::Arc;
+
+    // The latest lines
+    fn read_lines(lines: &Context, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<Line> {
+        let state = lines.snapshot(device, queue);
+        state.line_buffers[state.frame_num as usize]
+            .chunks_exact(std::mem::size_of::<Line>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect()
+    }
+
+    fn is_zeroed(line: &Line) -> bool {
+        bytemuck::bytes_of(line).iter().all(|byte| *byte == 0)
+    }
+
+    #[test]
+    #[ignore = "needs a GPU or lavapipe"]
+    fn resamples_the_lines_onto_a_new_grid() {
+        let (device, queue) = test_device();
+        let settings = Arc::new(Settings {
+            seed: Some("resample".to_string()),
+            ..Default::default()
+        });
+        let mut headless = Headless::new(&device, &queue, 200, 150, 200, 150, &settings).unwrap();
+        for _ in 0..30 {
+            headless.step(&device, &queue, 1.0 / 60.0);
+        }
+
+        let lines = &mut headless.flux_mut().lines;
+        let [columns, rows] = lines.grid_size;
+        let old_lines = read_lines(lines, &device, &queue);
+        assert!(!old_lines.iter().any(is_zeroed));
+
+        let grid = Grid::new(400, 300, 40);
+        assert_ne!([grid.columns, grid.rows], [columns, rows]);
+        lines.set_grid(&device, &queue, &grid);
+        let new_lines = read_lines(lines, &device, &queue);
+        assert_eq!(new_lines.len(), grid.line_count as usize);
+
+        // Each new line blends its old neighbours, so it stays within their
+        // bounds.
+        let (min, max) =
+            old_lines
+                .iter()
+                .fold(([f32::MAX; 2], [f32::MIN; 2]), |(min, max), line| {
+                    (
+                        [min[0].min(line.endpoint[0]), min[1].min(line.endpoint[1])],
+                        [max[0].max(line.endpoint[0]), max[1].max(line.endpoint[1])],
+                    )
+                });
+        for (index, line) in new_lines.iter().enumerate() {
+            assert!(!is_zeroed(line), "Line {} was reset: {:?}", index, line);
+            for axis in 0..2 {
+                let endpoint = line.endpoint[axis];
+                assert!(
+                    endpoint.is_finite()
+                        && endpoint >= min[axis] - 1e-4
+                        && endpoint <= max[axis] + 1e-4,
+                    "Line {} ended up at {:?}",
+                    index,
+                    line.endpoint
+                );
+            }
+        }
+
+        // The edges of the new grid map back onto the edges of the old one.
+        let corners =
+            |columns: u32, rows: u32| [0, columns - 1, (rows - 1) * columns, rows * columns - 1];
+        for (new_index, old_index) in corners(grid.columns, grid.rows)
+            .into_iter()
+            .zip(corners(columns, rows))
+        {
+            let (new_line, old_line) =
+                (new_lines[new_index as usize], old_lines[old_index as usize]);
+            for axis in 0..2 {
+                assert!(
+                    (new_line.endpoint[axis] - old_line.endpoint[axis]).abs() <= 1e-4,
+                    "The corner line {} ended up at {:?}, not {:?}",
+                    new_index,
+                    new_line.endpoint,
+                    old_line.endpoint
+                );
+            }
+        }
+    }
+}
@@ -2,6 +2,9 @@ pub mod color;
 pub mod fluid;
 pub mod lines;
 pub mod noise;
+pub mod obstacle;
+pub mod pressure;
+pub(crate) mod readback;
 pub mod texture;
 pub mod view;
 
@@ -1,4 +1,5 @@
-use crate::{grid, rng, settings};
+use crate::snapshot::{self, NoiseState};
+use crate::{audio, grid, rng, settings};
 
 use std::borrow::Cow;
 use std::sync::Arc;
@@ -15,11 +16,15 @@ pub struct NoiseGenerator {
 
     channel_settings: Vec<settings::Noise>,
     channels: Vec<NoiseChannel>,
+    modulation: audio::Modulation,
 
     uniform_buffer: wgpu::Buffer,
     channel_buffer: wgpu::Buffer,
+    linear_sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
     push_constants_buffer: wgpu::Buffer,
+    inject_noise_bind_group_layout: wgpu::BindGroupLayout,
     inject_noise_bind_group: wgpu::BindGroup,
 
     generate_noise_pipeline: wgpu::ComputePipeline,
@@ -28,10 +33,6 @@ pub struct NoiseGenerator {
 
 impl NoiseGenerator {
     pub fn resize(&mut self, device: &wgpu::Device, size: u32, scaling_ratio: grid::ScalingRatio) {
-        if scaling_ratio == self.scaling_ratio {
-            return;
-        }
-
         let (width, height) = (
             size * scaling_ratio.rounded_x(),
             size * scaling_ratio.rounded_y(),
@@ -42,8 +43,27 @@ impl NoiseGenerator {
             depth_or_array_layers: 1,
         };
 
+        if size == self.texture.size() {
+            return;
+        }
+
         let (texture, texture_view) = create_texture(device, &size);
 
+        self.bind_group = create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform_buffer,
+            &self.channel_buffer,
+            &texture_view,
+        );
+        self.inject_noise_bind_group = create_inject_noise_bind_group(
+            device,
+            &self.inject_noise_bind_group_layout,
+            &self.push_constants_buffer,
+            &texture_view,
+            &self.linear_sampler,
+        );
+
         self.scaling_ratio = scaling_ratio;
         self.texture = texture;
         self.texture_view = texture_view;
@@ -54,27 +74,66 @@ impl NoiseGenerator {
         self.channel_settings = new_settings.noise_channels.to_vec();
     }
 
+    // Scale the multipliers from the settings, for audio-reactive noise.
+    pub fn set_modulation(&mut self, modulation: &audio::Modulation) {
+        self.modulation.clone_from(modulation);
+    }
+
+    pub(crate) fn snapshot(&self) -> NoiseState {
+        NoiseState {
+            elapsed_time: self.elapsed_time,
+            channels: self
+                .channels
+                .iter()
+                .map(|channel| [channel.offset_1, channel.offset_2, channel.blend_factor])
+                .collect(),
+        }
+    }
+
+    // The buffers pick up the restored offsets on the next update.
+    pub(crate) fn restore(&mut self, state: &NoiseState) -> Result<(), snapshot::Problem> {
+        snapshot::check(
+            "number of noise channels",
+            state.channels.len(),
+            self.channels.len(),
+        )?;
+
+        self.elapsed_time = state.elapsed_time;
+        for (channel, [offset_1, offset_2, blend_factor]) in
+            self.channels.iter_mut().zip(&state.channels)
+        {
+            channel.offset_1 = *offset_1;
+            channel.offset_2 = *offset_2;
+            channel.blend_factor = *blend_factor;
+        }
+
+        Ok(())
+    }
+
     pub fn update_buffers(&mut self, queue: &wgpu::Queue, timestep: f32) {
         self.elapsed_time += timestep;
 
         self.channels
             .iter_mut()
             .zip(self.channel_settings.iter())
-            .for_each(|(channel, channel_settings)| {
+            .enumerate()
+            .for_each(|(index, (channel, channel_settings))| {
                 channel.tick(channel_settings, self.elapsed_time);
+                channel.multiplier *= self.modulation.get_noise_channel_multiplier(index);
             });
 
+        let uniforms = NoiseUniforms {
+            multiplier: self.uniforms.multiplier * self.modulation.noise_multiplier,
+            ..self.uniforms
+        };
+
         queue.write_buffer(
             &self.push_constants_buffer,
             0,
             bytemuck::cast_slice(&[0.0, 0.0, 0.0, timestep]),
         );
 
-        queue.write_buffer(
-            &self.uniform_buffer,
-            0,
-            bytemuck::cast_slice(&[self.uniforms]),
-        );
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
 
         queue.write_buffer(
             &self.channel_buffer,
@@ -245,36 +304,13 @@ impl NoiseGeneratorBuilder {
             ],
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("bind_group:noise"),
-            layout: &bind_group_layout,
-            entries: &[
-                // wgpu::BindGroupEntry {
-                //     binding: 2,
-                //     resource: wgpu::BindingResource::Sampler(&linear_sampler),
-                // },
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &uniform_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &channel_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
-                },
-            ],
-        });
+        let bind_group = create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform_buffer,
+            &channel_buffer,
+            &texture_view,
+        );
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("pipeline_layout:generate_noise"),
@@ -380,28 +416,13 @@ impl NoiseGeneratorBuilder {
                 ],
             });
 
-        let inject_noise_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Inject noise bind group"),
-            layout: &inject_noise_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &push_constants_buffer,
-                        offset: 0,
-                        size: None,
-                    }),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&linear_sampler),
-                },
-            ],
-        });
+        let inject_noise_bind_group = create_inject_noise_bind_group(
+            device,
+            &inject_noise_bind_group_layout,
+            &push_constants_buffer,
+            &texture_view,
+            &linear_sampler,
+        );
 
         let inject_noise_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -436,13 +457,17 @@ impl NoiseGeneratorBuilder {
             uniforms,
             channel_settings: self.channels,
             channels,
+            modulation: audio::Modulation::default(),
 
             uniform_buffer,
             channel_buffer,
             scaling_ratio: self.scaling_ratio,
             texture,
             texture_view,
+            linear_sampler,
+            bind_group_layout,
             bind_group,
+            inject_noise_bind_group_layout,
             inject_noise_bind_group,
             push_constants_buffer,
 
@@ -478,23 +503,99 @@ fn create_texture(
     (texture, texture_view)
 }
 
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    channel_buffer: &wgpu::Buffer,
+    texture_view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("bind_group:noise"),
+        layout,
+        entries: &[
+            // wgpu::BindGroupEntry {
+            //     binding: 2,
+            //     resource: wgpu::BindingResource::Sampler(&linear_sampler),
+            // },
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: uniform_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: channel_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+        ],
+    })
+}
+
+fn create_inject_noise_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    push_constants_buffer: &wgpu::Buffer,
+    texture_view: &wgpu::TextureView,
+    linear_sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Inject noise bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: push_constants_buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(linear_sampler),
+            },
+        ],
+    })
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct NoiseChannel {
-    scale: [f32; 2],   // 0
-    offset_1: f32,     // 8
-    offset_2: f32,     // 12
-    blend_factor: f32, //16
-    multiplier: f32,   // 20
-    _padding: [u32; 2], // 24
-                       // roundUp(8, 24) = 24 -> 32 for uniform
+    pub(crate) scale: [f32; 2],   // 0
+    pub(crate) offset_1: f32,     // 8
+    pub(crate) offset_2: f32,     // 12
+    pub(crate) blend_factor: f32, //16
+    pub(crate) multiplier: f32,   // 20
+    // The kind of noise, in the order of the constants in the shader
+    pub(crate) kind: u32,       // 24
+    pub(crate) octaves: u32,    // 28
+    pub(crate) lacunarity: f32, // 32
+    pub(crate) gain: f32,       // 36
+    pub(crate) warp: f32,       // 40
+    _padding: u32,              // 44
+                                // roundUp(8, 48) = 48
 }
 
 impl NoiseChannel {
     const BLEND_THRESHOLD: f32 = 1000.0;
 
     pub fn new(scaling_ratio: grid::ScalingRatio, channel_settings: &settings::Noise) -> Self {
-        Self {
+        let mut channel = Self {
             scale: [
                 channel_settings.scale * scaling_ratio.x(),
                 channel_settings.scale * scaling_ratio.y(),
@@ -503,8 +604,36 @@ impl NoiseChannel {
             offset_2: 0.0,
             blend_factor: 0.0,
             multiplier: channel_settings.multiplier,
-            _padding: [0; 2],
-        }
+            kind: 0,
+            octaves: 0,
+            lacunarity: 0.0,
+            gain: 0.0,
+            warp: 0.0,
+            _padding: 0,
+        };
+        channel.set_kind(channel_settings.kind);
+        channel
+    }
+
+    fn set_kind(&mut self, kind: settings::NoiseKind) {
+        use settings::NoiseKind::*;
+        (self.octaves, self.lacunarity, self.gain, self.warp) = (0, 0.0, 0.0, 0.0);
+        self.kind = match kind {
+            Simplex => 0,
+            Curl => 1,
+            Fbm {
+                octaves,
+                lacunarity,
+                gain,
+            } => {
+                (self.octaves, self.lacunarity, self.gain) = (octaves, lacunarity, gain);
+                2
+            }
+            DomainWarped { warp } => {
+                self.warp = warp;
+                3
+            }
+        };
     }
 
     pub fn tick(&mut self, channel_settings: &settings::Noise, elapsed_time: f32) {
@@ -512,6 +641,7 @@ impl NoiseChannel {
             * (1.0 + 0.15 * (0.01 * elapsed_time * std::f32::consts::TAU).sin());
         self.scale = [scale, scale];
         self.multiplier = channel_settings.multiplier;
+        self.set_kind(channel_settings.kind);
         self.offset_1 += channel_settings.offset_increment;
 
         if self.offset_1 > Self::BLEND_THRESHOLD {
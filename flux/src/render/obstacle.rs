@@ -0,0 +1,128 @@
+// Solid obstacles for the fluid to flow around, cut out of an image.
+
+use crate::settings::Obstacle;
+use image::RgbaImage;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Problem {
+    #[error("Failed to read obstacle image: {0}")]
+    ReadImage(#[from] std::io::Error),
+
+    #[error("Failed to decode obstacle image: {0}")]
+    DecodeImage(#[from] image::ImageError),
+}
+
+pub fn read_image(path: &std::path::Path) -> Result<RgbaImage, Problem> {
+    std::fs::read(path)
+        .map_err(Problem::ReadImage)
+        .and_then(|ref encoded_bytes| decode_image(encoded_bytes))
+        .inspect_err(|err| {
+            log::error!("Failed to load obstacle from {}: {}", path.display(), err);
+        })
+}
+
+pub fn decode_image(encoded_bytes: &[u8]) -> Result<RgbaImage, Problem> {
+    let image = image::load_from_memory(encoded_bytes)?;
+
+    log::debug!(
+        "Decoded obstacle (width: {}, height: {})",
+        image.width(),
+        image.height()
+    );
+
+    Ok(image.to_rgba8())
+}
+
+// Rasterize an obstacle onto a grid that covers the view, bottom row first.
+// Solid cells are 1.0, and the rest are 0.0.
+//
+// The grid covers the view zoomed in around its center by `zoom`, like the
+// lines do. The aspect ratio is the view’s, which can differ from the grid’s.
+pub fn rasterize(
+    image: &RgbaImage,
+    obstacle: &Obstacle,
+    [width, height]: [u32; 2],
+    aspect_ratio: f32,
+    zoom: f32,
+) -> Vec<f32> {
+    let (image_width, image_height) = image.dimensions();
+    let mut mask = vec![0.0; (width * height) as usize];
+    if image_width == 0 || image_height == 0 {
+        return mask;
+    }
+
+    // The size of the image, as a fraction of the view
+    let size_x = obstacle.scale * image_width as f32 / (image_height as f32 * aspect_ratio);
+    let size_y = obstacle.scale;
+    // The center of the image, with the y-axis pointing up
+    let [center_x, center_y] = [obstacle.position[0], 1.0 - obstacle.position[1]];
+
+    for y in 0..height {
+        for x in 0..width {
+            let view_x = 0.5 + zoom * ((x as f32 + 0.5) / width as f32 - 0.5);
+            let view_y = 0.5 + zoom * ((y as f32 + 0.5) / height as f32 - 0.5);
+            let u = (view_x - center_x) / size_x + 0.5;
+            let v = (view_y - center_y) / size_y + 0.5;
+            if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+                continue;
+            }
+
+            // Images store the top row first.
+            let pixel_x = ((u * image_width as f32) as u32).min(image_width - 1);
+            let pixel_y = (((1.0 - v) * image_height as f32) as u32).min(image_height - 1);
+            if image.get_pixel(pixel_x, pixel_y)[3] >= 128 {
+                mask[(y * width + x) as usize] = 1.0;
+            }
+        }
+    }
+
+    mask
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::Rgba;
+
+    // The left half is opaque, and the right half transparent.
+    fn half_opaque_image() -> RgbaImage {
+        RgbaImage::from_fn(4, 2, |x, _| {
+            Rgba([255, 255, 255, if x < 2 { 255 } else { 0 }])
+        })
+    }
+
+    #[test]
+    fn places_the_image_in_the_view() {
+        let image = half_opaque_image();
+
+        // Fill a 2:1 view with the 2:1 image.
+        let obstacle = Obstacle {
+            position: [0.5, 0.5],
+            scale: 1.0,
+            ..Default::default()
+        };
+        let mask = rasterize(&image, &obstacle, [4, 2], 2.0, 1.0);
+        assert_eq!(mask, [1.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+
+        // Shrink it into the top-left quarter.
+        let obstacle = Obstacle {
+            position: [0.25, 0.25],
+            scale: 0.5,
+            ..Default::default()
+        };
+        let mask = rasterize(&image, &obstacle, [8, 4], 2.0, 1.0);
+        let solid = (0..8 * 4)
+            .filter(|&index| mask[index] == 1.0)
+            .map(|index| (index % 8, index / 8))
+            .collect::<Vec<_>>();
+        assert_eq!(solid, [(0, 2), (1, 2), (0, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn rejects_broken_images() {
+        assert!(matches!(
+            decode_image(b"not an image"),
+            Err(Problem::DecodeImage(_))
+        ));
+    }
+}
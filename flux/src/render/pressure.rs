@@ -0,0 +1,748 @@
+// Solve for the pressure that makes the velocity divergence-free.
+//
+// The Jacobi solver updates every cell from its neighbours’ old values. The
+// red-black Gauss–Seidel solver updates the grid in two halves, like the
+// squares of a checkerboard, so that the second half reads the values that the
+// first half just wrote. The multigrid solver runs red-black sweeps on a stack
+// of coarser grids, so that smooth, large-scale errors decay as quickly as
+// the small ones.
+//
+// With a tolerance, the solvers dispatch indirectly. Every few iterations, a
+// reduction finds the largest residual, and once that drops below the
+// tolerance, the remaining dispatches run with zero workgroups.
+
+use crate::render::fluid;
+use crate::settings::{PressureSolver, Settings};
+
+use wgpu::util::DeviceExt;
+
+// Red-black sweeps before and after the coarse-grid correction.
+pub(crate) const PRE_SWEEPS: u32 = 2;
+pub(crate) const POST_SWEEPS: u32 = 2;
+// Red-black sweeps on the coarsest grid
+pub(crate) const COARSEST_SWEEPS: u32 = 8;
+
+pub(crate) const MAX_LEVELS: usize = 8;
+const MIN_LEVEL_SIZE: u32 = 4;
+
+// The size of each multigrid level, starting with the fluid itself. Each level
+// halves the one above it, so stop at odd sizes.
+pub(crate) fn get_level_sizes(width: u32, height: u32) -> Vec<(u32, u32)> {
+    let mut sizes = vec![(width, height)];
+    let (mut width, mut height) = (width, height);
+    while sizes.len() < MAX_LEVELS
+        && width.is_multiple_of(2)
+        && height.is_multiple_of(2)
+        && width / 2 >= MIN_LEVEL_SIZE
+        && height / 2 >= MIN_LEVEL_SIZE
+    {
+        width /= 2;
+        height /= 2;
+        sizes.push((width, height));
+    }
+    sizes
+}
+
+// How many times an iteration swaps the fluid’s pressure textures
+fn get_swaps_per_iteration(solver: PressureSolver) -> u32 {
+    match solver {
+        PressureSolver::Jacobi => 1,
+        PressureSolver::RedBlackGaussSeidel => 2,
+        // The sweeps, plus the prolongation
+        PressureSolver::Multigrid => 2 * (PRE_SWEEPS + POST_SWEEPS) + 1,
+    }
+}
+
+// Checking the residual costs about as much as a Jacobi iteration.
+fn get_check_interval(solver: PressureSolver) -> u32 {
+    match solver {
+        PressureSolver::Jacobi => 8,
+        PressureSolver::RedBlackGaussSeidel => 4,
+        PressureSolver::Multigrid => 1,
+    }
+}
+
+// The iterations to check the residual before. Skipping the rest of the
+// iterations has to leave the pressure in the same texture as running them,
+// so only check where the remaining iterations swap the textures an even
+// number of times.
+pub(crate) fn get_residual_checks(solver: PressureSolver, iterations: u32) -> Vec<u32> {
+    let swaps = get_swaps_per_iteration(solver);
+    let interval = get_check_interval(solver);
+
+    let mut checks = Vec::new();
+    let mut last_check = 0;
+    for iteration in 1..iterations {
+        if iteration - last_check >= interval
+            && (swaps * (iterations - iteration)).is_multiple_of(2)
+        {
+            checks.push(iteration);
+            last_check = iteration;
+        }
+    }
+    checks
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct RedBlackUniforms {
+    parity: u32,        // 0
+    _padding: [u32; 3], // 4
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ConvergenceUniforms {
+    tolerance: f32,                     // 0
+    level_count: u32,                   // 4
+    _padding: [u32; 2],                 // 8
+    workgroups: [[u32; 4]; MAX_LEVELS], // 16
+}
+
+// The x, y and z workgroup counts of an indirect dispatch
+const DISPATCH_ARGS_SIZE: u64 = 3 * 4;
+
+pub struct Solver {
+    solver: PressureSolver,
+    iterations: u32,
+    tolerance: Option<f32>,
+
+    layouts: Layouts,
+    levels: Vec<Level>,
+    transfers: Vec<Transfer>,
+    // The coarse textures, kept alive for the bind groups
+    textures: Vec<wgpu::Texture>,
+
+    red_black_bind_groups: [wgpu::BindGroup; 2],
+    convergence_uniform_buffer: wgpu::Buffer,
+    dispatch_buffer: wgpu::Buffer,
+    convergence_bind_group: wgpu::BindGroup,
+    residual_bind_group: wgpu::BindGroup,
+
+    jacobi_pipeline: wgpu::ComputePipeline,
+    red_black_pipeline: wgpu::ComputePipeline,
+    residual_pipeline: wgpu::ComputePipeline,
+    restrict_pipeline: wgpu::ComputePipeline,
+    prolongate_pipeline: wgpu::ComputePipeline,
+    measure_residual_pipeline: wgpu::ComputePipeline,
+    reset_pipeline: wgpu::ComputePipeline,
+    decide_pipeline: wgpu::ComputePipeline,
+}
+
+// The finest level reads and writes the fluid’s own divergence and pressure
+// textures.
+struct Level {
+    size: wgpu::Extent3d,
+    divergence: wgpu::BindGroup,
+    pressure: [wgpu::BindGroup; 2],
+}
+
+// Moving between a level and the coarser one below it
+struct Transfer {
+    // Write and read the residual of the finer level
+    out_residual: wgpu::BindGroup,
+    residual: wgpu::BindGroup,
+    // Write the right-hand side of the coarser level, and clear its pressure
+    restrict: wgpu::BindGroup,
+    // Read the coarser level’s pressure as a correction
+    correction: [wgpu::BindGroup; 2],
+}
+
+// Kept around to rebuild the levels when the fluid is resized.
+struct Layouts {
+    texture: wgpu::BindGroupLayout,
+    pressure: wgpu::BindGroupLayout,
+    out_texture: wgpu::BindGroupLayout,
+    restrict: wgpu::BindGroupLayout,
+}
+
+impl Solver {
+    // `uniform_layout` is the fluid’s layout for the FluidUniforms and samplers.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniform_layout: &wgpu::BindGroupLayout,
+        size: &wgpu::Extent3d,
+        divergence_view: &wgpu::TextureView,
+        pressure_views: &[wgpu::TextureView; 2],
+        settings: &Settings,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let storage_texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: wgpu::TextureFormat::R32Float,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        };
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage = wgpu::BufferBindingType::Storage { read_only: false };
+
+        let layouts = Layouts {
+            texture: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:pressure_texture"),
+                entries: &[texture_entry(0)],
+            }),
+            pressure: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:pressure_solver"),
+                entries: &[texture_entry(0), storage_texture_entry(1)],
+            }),
+            out_texture: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:pressure_out_texture"),
+                entries: &[storage_texture_entry(0)],
+            }),
+            restrict: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:restrict"),
+                entries: &[storage_texture_entry(0), storage_texture_entry(1)],
+            }),
+        };
+        let red_black_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bind_group_layout:red_black"),
+            entries: &[buffer_entry(0, wgpu::BufferBindingType::Uniform)],
+        });
+        let residual_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bind_group_layout:residual"),
+            entries: &[buffer_entry(0, storage)],
+        });
+        // Separate from the residual, so that the solver passes can dispatch
+        // from the buffer that this one writes.
+        let convergence_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("bind_group_layout:convergence"),
+                entries: &[
+                    buffer_entry(0, wgpu::BufferBindingType::Uniform),
+                    buffer_entry(1, storage),
+                    buffer_entry(2, storage),
+                ],
+            });
+
+        // Uniforms and buffers
+
+        let red_black_bind_groups = [0, 1].map(|parity| {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("uniform:red_black_{}", parity)),
+                contents: bytemuck::cast_slice(&[RedBlackUniforms {
+                    parity,
+                    _padding: [0; 3],
+                }]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("bind_group:red_black_{}", parity)),
+                layout: &red_black_layout,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+            })
+        });
+
+        let convergence_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("buffer:ConvergenceUniforms"),
+            size: std::mem::size_of::<ConvergenceUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let residual_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("buffer:residual"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let dispatch_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("buffer:pressure_dispatches"),
+            size: MAX_LEVELS as u64 * DISPATCH_ARGS_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        });
+
+        let convergence_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:convergence"),
+            layout: &convergence_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: convergence_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: residual_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: dispatch_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let residual_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("bind_group:residual"),
+            layout: &residual_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: residual_buffer.as_entire_binding(),
+            }],
+        });
+
+        // Pipelines
+
+        let create_pipeline =
+            |name: &str,
+             source: &'static str,
+             entry_point: &str,
+             bind_group_layouts: &[&wgpu::BindGroupLayout]| {
+                let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some(&format!("shader:{}", name)),
+                    source: wgpu::ShaderSource::Wgsl(fluid::include_fluid(source)),
+                });
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some(&format!("pipeline_layout:{}", name)),
+                    bind_group_layouts,
+                    push_constant_ranges: &[],
+                });
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(&format!("pipeline:{}", name)),
+                    layout: Some(&layout),
+                    module: &shader,
+                    entry_point: Some(entry_point),
+                    compilation_options: Default::default(),
+                    cache: None,
+                })
+            };
+
+        let jacobi_pipeline = create_pipeline(
+            "pressure",
+            include_str!("../../shader/solve_pressure.comp.wgsl"),
+            "main",
+            &[uniform_layout, &layouts.texture, &layouts.pressure],
+        );
+        let red_black_pipeline = create_pipeline(
+            "solve_pressure_red_black",
+            include_str!("../../shader/solve_pressure_red_black.comp.wgsl"),
+            "main",
+            &[
+                uniform_layout,
+                &layouts.texture,
+                &layouts.pressure,
+                &red_black_layout,
+            ],
+        );
+        let residual_pipeline = create_pipeline(
+            "residual",
+            include_str!("../../shader/residual.comp.wgsl"),
+            "main",
+            &[
+                uniform_layout,
+                &layouts.texture,
+                &layouts.pressure,
+                &layouts.out_texture,
+            ],
+        );
+        let restrict_pipeline = create_pipeline(
+            "restrict",
+            include_str!("../../shader/restrict.comp.wgsl"),
+            "main",
+            &[&layouts.texture, &layouts.restrict],
+        );
+        let prolongate_pipeline = create_pipeline(
+            "prolongate",
+            include_str!("../../shader/prolongate.comp.wgsl"),
+            "main",
+            &[uniform_layout, &layouts.texture, &layouts.pressure],
+        );
+        let measure_residual_pipeline = create_pipeline(
+            "measure_residual",
+            include_str!("../../shader/measure_residual.comp.wgsl"),
+            "main",
+            &[
+                uniform_layout,
+                &layouts.texture,
+                &layouts.pressure,
+                &residual_layout,
+            ],
+        );
+        let converge = include_str!("../../shader/converge.comp.wgsl");
+        let reset_pipeline =
+            create_pipeline("converge_reset", converge, "reset", &[&convergence_layout]);
+        let decide_pipeline = create_pipeline(
+            "converge_decide",
+            converge,
+            "decide",
+            &[&convergence_layout],
+        );
+
+        let (levels, transfers, textures) =
+            create_levels(device, &layouts, size, divergence_view, pressure_views);
+
+        let solver = Self {
+            solver: settings.pressure_solver,
+            iterations: settings.pressure_iterations,
+            tolerance: settings.pressure_tolerance,
+
+            layouts,
+            levels,
+            transfers,
+            textures,
+
+            red_black_bind_groups,
+            convergence_uniform_buffer,
+            dispatch_buffer,
+            convergence_bind_group,
+            residual_bind_group,
+
+            jacobi_pipeline,
+            red_black_pipeline,
+            residual_pipeline,
+            restrict_pipeline,
+            prolongate_pipeline,
+            measure_residual_pipeline,
+            reset_pipeline,
+            decide_pipeline,
+        };
+        solver.write_convergence_uniforms(queue);
+        solver
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, settings: &Settings) {
+        self.solver = settings.pressure_solver;
+        self.iterations = settings.pressure_iterations;
+        self.tolerance = settings.pressure_tolerance;
+        self.write_convergence_uniforms(queue);
+    }
+
+    // Rebuild the levels for the fluid’s new textures.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: &wgpu::Extent3d,
+        divergence_view: &wgpu::TextureView,
+        pressure_views: &[wgpu::TextureView; 2],
+    ) {
+        let (levels, transfers, textures) =
+            create_levels(device, &self.layouts, size, divergence_view, pressure_views);
+        self.levels = levels;
+        self.transfers = transfers;
+        self.textures = textures;
+        self.write_convergence_uniforms(queue);
+    }
+
+    fn write_convergence_uniforms(&self, queue: &wgpu::Queue) {
+        let mut workgroups = [[0; 4]; MAX_LEVELS];
+        for (level, workgroup) in self.levels.iter().zip(workgroups.iter_mut()) {
+            let (x, y) = get_workgroups(&level.size);
+            *workgroup = [x, y, 1, 0];
+        }
+
+        queue.write_buffer(
+            &self.convergence_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[ConvergenceUniforms {
+                tolerance: self.tolerance.unwrap_or(0.0),
+                level_count: self.levels.len() as u32,
+                _padding: [0; 2],
+                workgroups,
+            }]),
+        );
+    }
+
+    // Run the solver, starting from the fluid’s pressure texture at
+    // `pressure_index`. The index ends up pointing at the result.
+    pub fn solve<'cpass>(
+        &'cpass self,
+        cpass: &mut wgpu::ComputePass<'cpass>,
+        uniform_bind_group: &'cpass wgpu::BindGroup,
+        pressure_index: &mut usize,
+    ) {
+        let checks = match self.tolerance {
+            Some(_) => {
+                cpass.set_pipeline(&self.reset_pipeline);
+                cpass.set_bind_group(0, &self.convergence_bind_group, &[]);
+                cpass.dispatch_workgroups(1, 1, 1);
+                get_residual_checks(self.solver, self.iterations)
+            }
+            None => Vec::new(),
+        };
+
+        for iteration in 0..self.iterations {
+            if checks.contains(&iteration) {
+                self.check_residual(cpass, uniform_bind_group, *pressure_index);
+            }
+
+            match self.solver {
+                PressureSolver::Jacobi => {
+                    let level = &self.levels[0];
+                    cpass.set_pipeline(&self.jacobi_pipeline);
+                    cpass.set_bind_group(0, uniform_bind_group, &[]);
+                    cpass.set_bind_group(1, &level.divergence, &[]);
+                    cpass.set_bind_group(2, &level.pressure[*pressure_index], &[]);
+                    self.dispatch(cpass, 0);
+                    *pressure_index = 1 - *pressure_index;
+                }
+                PressureSolver::RedBlackGaussSeidel => {
+                    self.red_black_sweep(cpass, uniform_bind_group, 0, pressure_index);
+                }
+                PressureSolver::Multigrid => {
+                    self.v_cycle(cpass, uniform_bind_group, 0, pressure_index);
+                }
+            }
+        }
+    }
+
+    // Both halves of a red-black iteration on a level
+    fn red_black_sweep<'cpass>(
+        &'cpass self,
+        cpass: &mut wgpu::ComputePass<'cpass>,
+        uniform_bind_group: &'cpass wgpu::BindGroup,
+        level_index: usize,
+        pressure_index: &mut usize,
+    ) {
+        let level = &self.levels[level_index];
+        cpass.set_pipeline(&self.red_black_pipeline);
+        cpass.set_bind_group(0, uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &level.divergence, &[]);
+
+        for parity_bind_group in self.red_black_bind_groups.iter() {
+            cpass.set_bind_group(2, &level.pressure[*pressure_index], &[]);
+            cpass.set_bind_group(3, parity_bind_group, &[]);
+            self.dispatch(cpass, level_index);
+            *pressure_index = 1 - *pressure_index;
+        }
+    }
+
+    fn v_cycle<'cpass>(
+        &'cpass self,
+        cpass: &mut wgpu::ComputePass<'cpass>,
+        uniform_bind_group: &'cpass wgpu::BindGroup,
+        level_index: usize,
+        pressure_index: &mut usize,
+    ) {
+        let Some(transfer) = self.transfers.get(level_index) else {
+            for _ in 0..COARSEST_SWEEPS {
+                self.red_black_sweep(cpass, uniform_bind_group, level_index, pressure_index);
+            }
+            return;
+        };
+        let level = &self.levels[level_index];
+
+        for _ in 0..PRE_SWEEPS {
+            self.red_black_sweep(cpass, uniform_bind_group, level_index, pressure_index);
+        }
+
+        cpass.set_pipeline(&self.residual_pipeline);
+        cpass.set_bind_group(0, uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &level.divergence, &[]);
+        cpass.set_bind_group(2, &level.pressure[*pressure_index], &[]);
+        cpass.set_bind_group(3, &transfer.out_residual, &[]);
+        self.dispatch(cpass, level_index);
+
+        // This clears the coarse pressure at index 0.
+        cpass.set_pipeline(&self.restrict_pipeline);
+        cpass.set_bind_group(0, &transfer.residual, &[]);
+        cpass.set_bind_group(1, &transfer.restrict, &[]);
+        self.dispatch(cpass, level_index + 1);
+
+        let mut coarse_pressure_index = 0;
+        self.v_cycle(
+            cpass,
+            uniform_bind_group,
+            level_index + 1,
+            &mut coarse_pressure_index,
+        );
+
+        cpass.set_pipeline(&self.prolongate_pipeline);
+        cpass.set_bind_group(0, uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &transfer.correction[coarse_pressure_index], &[]);
+        cpass.set_bind_group(2, &level.pressure[*pressure_index], &[]);
+        self.dispatch(cpass, level_index);
+        *pressure_index = 1 - *pressure_index;
+
+        for _ in 0..POST_SWEEPS {
+            self.red_black_sweep(cpass, uniform_bind_group, level_index, pressure_index);
+        }
+    }
+
+    // Measure the residual on the finest level, and skip the remaining
+    // dispatches if it’s small enough.
+    fn check_residual<'cpass>(
+        &'cpass self,
+        cpass: &mut wgpu::ComputePass<'cpass>,
+        uniform_bind_group: &'cpass wgpu::BindGroup,
+        pressure_index: usize,
+    ) {
+        let level = &self.levels[0];
+        cpass.set_pipeline(&self.measure_residual_pipeline);
+        cpass.set_bind_group(0, uniform_bind_group, &[]);
+        cpass.set_bind_group(1, &level.divergence, &[]);
+        cpass.set_bind_group(2, &level.pressure[pressure_index], &[]);
+        cpass.set_bind_group(3, &self.residual_bind_group, &[]);
+        self.dispatch(cpass, 0);
+
+        cpass.set_pipeline(&self.decide_pipeline);
+        cpass.set_bind_group(0, &self.convergence_bind_group, &[]);
+        cpass.dispatch_workgroups(1, 1, 1);
+    }
+
+    // Dispatch over a level, indirectly if the solve can stop early.
+    fn dispatch(&self, cpass: &mut wgpu::ComputePass<'_>, level_index: usize) {
+        if self.tolerance.is_some() {
+            cpass.dispatch_workgroups_indirect(
+                &self.dispatch_buffer,
+                level_index as u64 * DISPATCH_ARGS_SIZE,
+            );
+        } else {
+            let (x, y) = get_workgroups(&self.levels[level_index].size);
+            cpass.dispatch_workgroups(x, y, 1);
+        }
+    }
+}
+
+fn get_workgroups(size: &wgpu::Extent3d) -> (u32, u32) {
+    (size.width.div_ceil(16), size.height.div_ceil(16))
+}
+
+fn create_levels(
+    device: &wgpu::Device,
+    layouts: &Layouts,
+    size: &wgpu::Extent3d,
+    divergence_view: &wgpu::TextureView,
+    pressure_views: &[wgpu::TextureView; 2],
+) -> (Vec<Level>, Vec<Transfer>, Vec<wgpu::Texture>) {
+    let sizes = get_level_sizes(size.width, size.height);
+
+    let mut textures = Vec::new();
+    let mut create_view = |name: String, (width, height): (u32, u32)| {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&format!("texture:{}", name)),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            view_formats: &[],
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(&format!("view:{}", name)),
+            ..Default::default()
+        });
+        textures.push(texture);
+        view
+    };
+
+    // The divergence and pressure views of each level, and the residual of
+    // each level but the coarsest
+    let mut views = vec![(divergence_view.clone(), pressure_views.clone())];
+    for (index, &size) in sizes.iter().enumerate().skip(1) {
+        views.push((
+            create_view(format!("divergence_level_{}", index), size),
+            [0, 1].map(|i| create_view(format!("pressure_level_{}_{}", index, i), size)),
+        ));
+    }
+    let residual_views: Vec<_> = sizes[..sizes.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(index, &size)| create_view(format!("residual_level_{}", index), size))
+        .collect();
+
+    let bind_group = |name: String, layout, views: &[&wgpu::TextureView]| {
+        let entries: Vec<_> = views
+            .iter()
+            .enumerate()
+            .map(|(binding, view)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("bind_group:{}", name)),
+            layout,
+            entries: &entries,
+        })
+    };
+
+    let levels = sizes
+        .iter()
+        .zip(views.iter())
+        .enumerate()
+        .map(
+            |(index, (&(width, height), (divergence, pressure)))| Level {
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                divergence: bind_group(
+                    format!("divergence_level_{}", index),
+                    &layouts.texture,
+                    &[divergence],
+                ),
+                pressure: [0, 1].map(|i| {
+                    bind_group(
+                        format!("pressure_level_{}_{}", index, i),
+                        &layouts.pressure,
+                        &[&pressure[i], &pressure[1 - i]],
+                    )
+                }),
+            },
+        )
+        .collect();
+
+    let transfers = residual_views
+        .iter()
+        .enumerate()
+        .map(|(index, residual)| {
+            let (coarse_divergence, coarse_pressure) = &views[index + 1];
+            Transfer {
+                out_residual: bind_group(
+                    format!("out_residual_level_{}", index),
+                    &layouts.out_texture,
+                    &[residual],
+                ),
+                residual: bind_group(
+                    format!("residual_level_{}", index),
+                    &layouts.texture,
+                    &[residual],
+                ),
+                restrict: bind_group(
+                    format!("restrict_level_{}", index),
+                    &layouts.restrict,
+                    &[coarse_divergence, &coarse_pressure[0]],
+                ),
+                correction: [0, 1].map(|i| {
+                    bind_group(
+                        format!("correction_level_{}_{}", index, i),
+                        &layouts.texture,
+                        &[&coarse_pressure[i]],
+                    )
+                }),
+            }
+        })
+        .collect();
+
+    (levels, transfers, textures)
+}
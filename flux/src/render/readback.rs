@@ -0,0 +1,121 @@
+// Copy GPU resources back to the CPU.
+//
+// These block until the copy is done, so keep them out of the frame loop.
+
+use std::sync::mpsc;
+
+// Read the contents of a texture, without any row padding.
+//
+// The texture needs COPY_SRC usage.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Vec<u8> {
+    let size = texture.size();
+    let bytes_per_pixel = texture
+        .format()
+        .block_copy_size(None)
+        .expect("Can’t read back depth or compressed textures");
+    let unpadded_bytes_per_row = bytes_per_pixel * size.width;
+    // Rows copied into a buffer must be aligned to COPY_BYTES_PER_ROW_ALIGNMENT.
+    let padded_bytes_per_row =
+        unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("buffer:readback"),
+        size: u64::from(padded_bytes_per_row) * u64::from(size.height),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("flux:readback"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::TexelCopyBufferInfo {
+            buffer: &buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(size.height),
+            },
+        },
+        size,
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let padded_bytes = map_buffer(device, &buffer);
+    padded_bytes
+        .chunks_exact(padded_bytes_per_row as usize)
+        .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+        .copied()
+        .collect()
+}
+
+// Read the contents of a buffer.
+//
+// The buffer needs COPY_SRC usage.
+pub fn read_buffer(device: &wgpu::Device, queue: &wgpu::Queue, source: &wgpu::Buffer) -> Vec<u8> {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("buffer:readback"),
+        size: source.size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("flux:readback"),
+    });
+    encoder.copy_buffer_to_buffer(source, 0, &buffer, 0, source.size());
+    queue.submit(Some(encoder.finish()));
+
+    map_buffer(device, &buffer)
+}
+
+// Write tightly-packed data, as returned by `read_texture`, into a texture.
+pub fn write_texture(queue: &wgpu::Queue, texture: &wgpu::Texture, data: &[u8]) {
+    let size = texture.size();
+    let bytes_per_pixel = texture
+        .format()
+        .block_copy_size(None)
+        .expect("Can’t write depth or compressed textures");
+
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        data,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(bytes_per_pixel * size.width),
+            rows_per_image: Some(size.height),
+        },
+        size,
+    );
+}
+
+fn map_buffer(device: &wgpu::Device, buffer: &wgpu::Buffer) -> Vec<u8> {
+    let buffer_slice = buffer.slice(..);
+    let (tx, rx) = mpsc::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = tx.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    rx.recv()
+        .expect("Failed to receive the mapped buffer")
+        .expect("Failed to map the readback buffer");
+
+    let bytes = buffer_slice.get_mapped_range().to_vec();
+    buffer.unmap();
+    bytes
+}
@@ -4,6 +4,7 @@ use wgpu::util::DeviceExt;
 pub struct Context {
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
     texture_bind_groups: Vec<(String, wgpu::BindGroup)>,
     sampler: wgpu::Sampler,
     pipeline_layout: wgpu::PipelineLayout,
@@ -117,22 +118,8 @@ impl Context {
             ],
         });
 
-        let texture_bind_groups = texture_views
-            .iter()
-            .map(|(name, texture_view)| {
-                (
-                    name.to_string(),
-                    device.create_bind_group(&wgpu::BindGroupDescriptor {
-                        label: Some("texture"),
-                        layout: &texture_bind_group_layout,
-                        entries: &[wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureView(texture_view),
-                        }],
-                    }),
-                )
-            })
-            .collect();
+        let texture_bind_groups =
+            create_texture_bind_groups(device, &texture_bind_group_layout, texture_views);
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
@@ -175,6 +162,7 @@ impl Context {
         Self {
             bind_group_layout,
             bind_group,
+            texture_bind_group_layout,
             texture_bind_groups,
             sampler,
             pipeline_layout,
@@ -182,6 +170,16 @@ impl Context {
         }
     }
 
+    // Replace the textures, after they’ve been resized.
+    pub fn set_texture_views(
+        &mut self,
+        device: &wgpu::Device,
+        texture_views: &[(&str, &wgpu::TextureView)],
+    ) {
+        self.texture_bind_groups =
+            create_texture_bind_groups(device, &self.texture_bind_group_layout, texture_views);
+    }
+
     pub fn draw_texture<'rpass>(
         &'rpass self,
         _device: &wgpu::Device,
@@ -202,3 +200,26 @@ impl Context {
         }
     }
 }
+
+fn create_texture_bind_groups(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    texture_views: &[(&str, &wgpu::TextureView)],
+) -> Vec<(String, wgpu::BindGroup)> {
+    texture_views
+        .iter()
+        .map(|(name, texture_view)| {
+            (
+                name.to_string(),
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("texture"),
+                    layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(texture_view),
+                    }],
+                }),
+            )
+        })
+        .collect()
+}
@@ -12,12 +12,80 @@ use std::cell::RefCell;
 use std::thread_local;
 
 thread_local!(
-    static FLUX_RNG: RefCell<Pcg32> = {
-        let rng = Pcg32::from_rng(&mut rand::rng());
+    static FLUX_RNG: RefCell<SharedRng> = {
+        let rng = SharedRng::from_rng(&mut rand::rng());
         RefCell::new(rng)
     }
 );
 
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+// The same generator as `Pcg32`, so seeds give the same sequences, but with
+// its state out in the open for snapshots.
+struct SharedRng {
+    state: u64,
+    increment: u64,
+}
+
+impl SharedRng {
+    fn step(&mut self) {
+        self.state = self
+            .state
+            .wrapping_mul(MULTIPLIER)
+            .wrapping_add(self.increment);
+    }
+}
+
+impl SeedableRng for SharedRng {
+    type Seed = [u8; 16];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        let (state, increment) = seed.split_at(8);
+        let state = u64::from_le_bytes(state.try_into().unwrap());
+        // The increment must be odd
+        let increment = u64::from_le_bytes(increment.try_into().unwrap()) | 1;
+
+        let mut rng = SharedRng {
+            state: state.wrapping_add(increment),
+            increment,
+        };
+        rng.step();
+        rng
+    }
+}
+
+impl RngCore for SharedRng {
+    fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.step();
+
+        // XSH RR: an xorshift of the high bits, then a random rotation
+        let rotation = (state >> 59) as u32;
+        let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+        xorshifted.rotate_right(rotation)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = u64::from(self.next_u32());
+        let high = u64::from(self.next_u32());
+        (high << 32) | low
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let mut chunks = dest.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes());
+        }
+
+        let rest = chunks.into_remainder();
+        if rest.len() > 4 {
+            rest.copy_from_slice(&self.next_u64().to_le_bytes()[..rest.len()]);
+        } else if !rest.is_empty() {
+            rest.copy_from_slice(&self.next_u32().to_le_bytes()[..rest.len()]);
+        }
+    }
+}
+
 pub fn init_from_seed(optional_seed: &Option<String>) {
     let seed = optional_seed.as_ref().cloned().unwrap_or_else(|| {
         rand::rng()
@@ -36,3 +104,46 @@ where
 {
     FLUX_RNG.with(|rng| rng.borrow_mut().random::<T>())
 }
+
+// A standalone generator, for randomness that shouldn’t disturb the shared
+// one.
+pub fn from_seed(optional_seed: Option<&str>) -> Pcg32 {
+    match optional_seed {
+        Some(seed) => Seeder::from(seed).into_rng(),
+        None => Pcg32::from_rng(&mut rand::rng()),
+    }
+}
+
+// The state of the shared generator, to save it in snapshots.
+pub fn state() -> [u64; 2] {
+    FLUX_RNG.with(|rng| {
+        let rng = rng.borrow();
+        [rng.state, rng.increment]
+    })
+}
+
+pub fn set_state([state, increment]: [u64; 2]) {
+    FLUX_RNG.with(|rng| rng.replace(SharedRng { state, increment }));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_pcg32() {
+        let mut shared: SharedRng = Seeder::from("flux").into_rng();
+        let mut pcg: Pcg32 = Seeder::from("flux").into_rng();
+
+        for _ in 0..16 {
+            assert_eq!(shared.next_u32(), pcg.next_u32());
+        }
+        assert_eq!(shared.next_u64(), pcg.next_u64());
+
+        let mut shared_bytes = [0; 13];
+        let mut pcg_bytes = [0; 13];
+        shared.fill_bytes(&mut shared_bytes);
+        pcg.fill_bytes(&mut pcg_bytes);
+        assert_eq!(shared_bytes, pcg_bytes);
+    }
+}
@@ -11,11 +11,25 @@ pub struct Settings {
     pub fluid_timestep: f32,
     pub viscosity: f32,
     pub velocity_dissipation: f32,
+    // How hard to spin up the small eddies that the fluid loses to advection
+    // and diffusion. Zero turns off the vorticity confinement.
+    pub vorticity_strength: f32,
     pub pressure_mode: PressureMode,
+    pub boundary: Boundary,
     pub diffusion_iterations: u32,
+    // Jacobi iterations, red-black sweeps or V-cycles, depending on the solver
     pub pressure_iterations: u32,
+    pub pressure_solver: PressureSolver,
+    // Stop solving for the pressure once the largest residual drops below
+    // this. The GPU checks every few iterations, without a round trip to the
+    // CPU.
+    pub pressure_tolerance: Option<f32>,
+    // A solid shape for the fluid to flow around, like a logo
+    pub obstacle: Option<Obstacle>,
 
     pub color_mode: ColorMode,
+    pub image_color_mode: ImageColorMode,
+    pub color_transition: ColorTransition,
 
     pub line_length: f32,
     pub line_width: f32,
@@ -26,6 +40,8 @@ pub struct Settings {
 
     pub noise_multiplier: f32,
     pub noise_channels: Vec<Noise>,
+
+    pub audio: Audio,
 }
 
 impl Default for Settings {
@@ -38,10 +54,17 @@ impl Default for Settings {
             fluid_timestep: 1.0 / 60.0,
             viscosity: 5.0,
             velocity_dissipation: 0.0,
+            vorticity_strength: 0.0,
             pressure_mode: PressureMode::ClearWith(0.0),
+            boundary: Boundary::FreeSlip,
             diffusion_iterations: 3,
             pressure_iterations: 19,
+            pressure_solver: PressureSolver::Jacobi,
+            pressure_tolerance: None,
+            obstacle: None,
             color_mode: ColorMode::Preset(ColorPreset::Original),
+            image_color_mode: ImageColorMode::Texture,
+            color_transition: ColorTransition::default(),
             line_length: 450.0,
             line_width: 9.0,
             line_begin_offset: 0.4,
@@ -54,22 +77,273 @@ impl Default for Settings {
                     scale: 2.8,
                     multiplier: 1.0,
                     offset_increment: 0.001,
+                    kind: NoiseKind::Simplex,
                 },
                 Noise {
                     scale: 15.0,
                     multiplier: 0.7,
                     offset_increment: 0.001 * 6.0,
+                    kind: NoiseKind::Simplex,
                 },
                 Noise {
                     scale: 30.0,
                     multiplier: 0.5,
                     offset_increment: 0.001 * 12.0,
+                    kind: NoiseKind::Simplex,
                 },
             ],
+            audio: Audio::default(),
         }
     }
 }
 
+impl Settings {
+    // Check for values that would break the simulation or the GPU pipelines.
+    // Errors name the offending field as it appears in the serialized settings.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        check_finite("fluidFrameRate", self.fluid_frame_rate)?;
+        check_positive("fluidTimestep", self.fluid_timestep)?;
+        // The viscosity is used as a divisor in the diffusion solver.
+        check_positive("viscosity", self.viscosity)?;
+        check_finite("velocityDissipation", self.velocity_dissipation)?;
+        check_non_negative("vorticityStrength", self.vorticity_strength)?;
+        if let PressureMode::ClearWith(pressure) = self.pressure_mode {
+            check_finite("pressureMode.ClearWith", pressure)?;
+        }
+        if let Some(tolerance) = self.pressure_tolerance {
+            check_positive("pressureTolerance", tolerance)?;
+        }
+
+        if let Some(obstacle) = &self.obstacle {
+            check_finite("obstacle.position", obstacle.position[0])?;
+            check_finite("obstacle.position", obstacle.position[1])?;
+            check_positive("obstacle.scale", obstacle.scale)?;
+        }
+
+        // The fluid textures are processed in 16x16 workgroups.
+        check_non_zero("fluidSize", self.fluid_size)?;
+        if !self.fluid_size.is_multiple_of(FLUID_WORKGROUP_SIZE) {
+            return Err(ValidationError::new(
+                "fluidSize",
+                ValidationErrorKind::NotAMultipleOf(FLUID_WORKGROUP_SIZE),
+            ));
+        }
+
+        if let ColorMode::Palette(palette) = &self.color_mode {
+            if palette.stops.is_empty() {
+                return Err(ValidationError::new(
+                    "colorMode.Palette.stops",
+                    ValidationErrorKind::Empty,
+                ));
+            }
+            for (index, stop) in palette.stops.iter().enumerate() {
+                let field = |name| format!("colorMode.Palette.stops[{}].{}", index, name);
+                for component in stop.color {
+                    check_finite(&field("color"), component)?;
+                }
+                if let Some(position) = stop.position {
+                    check_finite(&field("position"), position)?;
+                }
+            }
+        }
+
+        if let ImageColorMode::Palette { size } = self.image_color_mode {
+            check_non_zero("imageColorMode.Palette.size", size)?;
+        }
+
+        check_finite("colorTransition.duration", self.color_transition.duration)?;
+        if self.color_transition.duration < 0.0 {
+            return Err(ValidationError::new(
+                "colorTransition.duration",
+                ValidationErrorKind::Negative,
+            ));
+        }
+
+        check_finite("lineLength", self.line_length)?;
+        check_finite("lineWidth", self.line_width)?;
+        check_finite("lineBeginOffset", self.line_begin_offset)?;
+        check_finite("lineVariance", self.line_variance)?;
+        check_non_zero("gridSpacing", self.grid_spacing)?;
+        check_finite("viewScale", self.view_scale)?;
+
+        check_finite("noiseMultiplier", self.noise_multiplier)?;
+        if self.noise_channels.is_empty() {
+            return Err(ValidationError::new(
+                "noiseChannels",
+                ValidationErrorKind::Empty,
+            ));
+        }
+        for (index, channel) in self.noise_channels.iter().enumerate() {
+            let field = |name| format!("noiseChannels[{}].{}", index, name);
+            check_finite(&field("scale"), channel.scale)?;
+            check_finite(&field("multiplier"), channel.multiplier)?;
+            check_finite(&field("offsetIncrement"), channel.offset_increment)?;
+            match channel.kind {
+                NoiseKind::Simplex | NoiseKind::Curl => (),
+                NoiseKind::Fbm {
+                    octaves,
+                    lacunarity,
+                    gain,
+                } => {
+                    check_non_zero(&field("kind.Fbm.octaves"), octaves)?;
+                    if octaves > MAX_NOISE_OCTAVES {
+                        return Err(ValidationError::new(
+                            field("kind.Fbm.octaves"),
+                            ValidationErrorKind::TooLarge(MAX_NOISE_OCTAVES),
+                        ));
+                    }
+                    check_positive(&field("kind.Fbm.lacunarity"), lacunarity)?;
+                    check_positive(&field("kind.Fbm.gain"), gain)?;
+                }
+                NoiseKind::DomainWarped { warp } => {
+                    check_finite(&field("kind.DomainWarped.warp"), warp)?;
+                }
+            }
+        }
+
+        self.validate_audio()?;
+
+        Ok(())
+    }
+
+    fn validate_audio(&self) -> Result<(), ValidationError> {
+        let audio = &self.audio;
+
+        for (index, [low, high]) in audio.bands.iter().enumerate() {
+            let field = format!("audio.bands[{}]", index);
+            check_finite(&field, *low)?;
+            check_positive(&field, *high)?;
+            if *low < 0.0 {
+                return Err(ValidationError::new(field, ValidationErrorKind::Negative));
+            }
+            if low >= high {
+                return Err(ValidationError::new(field, ValidationErrorKind::EmptyRange));
+            }
+        }
+
+        check_non_negative("audio.attack", audio.attack)?;
+        check_non_negative("audio.release", audio.release)?;
+        check_positive("audio.onsetThreshold", audio.onset_threshold)?;
+        check_non_negative("audio.onsetDecay", audio.onset_decay)?;
+
+        for (index, mapping) in audio.mappings.iter().enumerate() {
+            let field = |name| format!("audio.mappings[{}].{}", index, name);
+            check_finite(&field("amount"), mapping.amount)?;
+            if let AudioSource::Band(band) = mapping.source {
+                check_index(&field("source.Band"), band, audio.bands.len())?;
+            }
+            if let AudioTarget::NoiseChannelMultiplier(channel) = mapping.target {
+                check_index(
+                    &field("target.NoiseChannelMultiplier"),
+                    channel,
+                    self.noise_channels.len(),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+const FLUID_WORKGROUP_SIZE: u32 = 16;
+
+// The shader stops adding octaves of fBm noise after this many.
+pub const MAX_NOISE_OCTAVES: u32 = 8;
+
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("Invalid setting `{field}`: {kind}")]
+pub struct ValidationError {
+    pub field: String,
+    pub kind: ValidationErrorKind,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, thiserror::Error)]
+pub enum ValidationErrorKind {
+    #[error("must be a finite number")]
+    NotFinite,
+
+    #[error("must be greater than zero")]
+    NotPositive,
+
+    #[error("must not be negative")]
+    Negative,
+
+    #[error("must not be zero")]
+    Zero,
+
+    #[error("must be a multiple of {0}")]
+    NotAMultipleOf(u32),
+
+    #[error("must not be empty")]
+    Empty,
+
+    #[error("must start below where it ends")]
+    EmptyRange,
+
+    #[error("must be less than {0}")]
+    OutOfRange(usize),
+
+    #[error("must not be greater than {0}")]
+    TooLarge(u32),
+}
+
+impl ValidationError {
+    fn new(field: impl Into<String>, kind: ValidationErrorKind) -> Self {
+        Self {
+            field: field.into(),
+            kind,
+        }
+    }
+}
+
+fn check_finite(field: &str, value: f32) -> Result<(), ValidationError> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(ValidationError::new(field, ValidationErrorKind::NotFinite))
+    }
+}
+
+fn check_positive(field: &str, value: f32) -> Result<(), ValidationError> {
+    check_finite(field, value)?;
+    if value > 0.0 {
+        Ok(())
+    } else {
+        Err(ValidationError::new(
+            field,
+            ValidationErrorKind::NotPositive,
+        ))
+    }
+}
+
+fn check_non_negative(field: &str, value: f32) -> Result<(), ValidationError> {
+    check_finite(field, value)?;
+    if value >= 0.0 {
+        Ok(())
+    } else {
+        Err(ValidationError::new(field, ValidationErrorKind::Negative))
+    }
+}
+
+fn check_index(field: &str, index: usize, len: usize) -> Result<(), ValidationError> {
+    if index < len {
+        Ok(())
+    } else {
+        Err(ValidationError::new(
+            field,
+            ValidationErrorKind::OutOfRange(len),
+        ))
+    }
+}
+
+fn check_non_zero(field: &str, value: u32) -> Result<(), ValidationError> {
+    if value != 0 {
+        Ok(())
+    } else {
+        Err(ValidationError::new(field, ValidationErrorKind::Zero))
+    }
+}
+
 #[derive(Clone, Default, Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub enum Mode {
     #[default]
@@ -78,6 +352,7 @@ pub enum Mode {
     DebugFluid,
     DebugPressure,
     DebugDivergence,
+    DebugVorticity,
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -92,10 +367,66 @@ impl Default for PressureMode {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+// What the fluid does at the edges of the screen
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum Boundary {
+    // Walls that the fluid sticks to
+    Closed,
+    // Walls that the fluid slides along
+    #[default]
+    FreeSlip,
+    // The fluid flows out of the screen, into still air with zero pressure.
+    Open,
+    // The fluid wraps around to the opposite edge.
+    Periodic,
+}
+
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum PressureSolver {
+    // Cheap iterations, but large grids need a lot of them to settle.
+    #[default]
+    Jacobi,
+    // Updates the grid in a checkerboard pattern, so that half the cells see
+    // their neighbours’ new values. Converges about twice as fast per
+    // iteration, but each iteration takes two passes over the grid.
+    RedBlackGaussSeidel,
+    // Smooths the error on a stack of coarser grids, which clears up the
+    // large-scale divergence that the other solvers leave behind. Each V-cycle
+    // costs about as much as a dozen Jacobi iterations, and two or three are
+    // usually enough.
+    Multigrid,
+}
+
+// Cut out of an image. Pixels that are at least half opaque are solid.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Obstacle {
+    // On the web, this is a URL. The host fetches the image and passes it to
+    // `Flux::set_obstacle_image` instead.
+    pub image: std::path::PathBuf,
+    // The center of the image, as a fraction of the view’s width and height,
+    // measured from the top-left corner.
+    pub position: [f32; 2],
+    // The height of the image, as a fraction of the view’s height. The width
+    // follows the image’s aspect ratio.
+    pub scale: f32,
+}
+
+impl Default for Obstacle {
+    fn default() -> Self {
+        Self {
+            image: std::path::PathBuf::new(),
+            position: [0.5, 0.5],
+            scale: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum ColorMode {
     Preset(ColorPreset),
     ImageFile(std::path::PathBuf),
+    Palette(Palette),
 }
 
 impl Default for ColorMode {
@@ -110,6 +441,75 @@ impl From<ColorMode> for u32 {
             ColorMode::Preset(ColorPreset::Original) => 0,
             ColorMode::Preset(_) => 1,
             ColorMode::ImageFile(_) => 2,
+            ColorMode::Palette(_) => 1,
+        }
+    }
+}
+
+// How to color the lines with an image.
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum ImageColorMode {
+    // Sample the image at the line’s velocity.
+    #[default]
+    Texture,
+    // Extract a palette of dominant colors for the color wheel.
+    Palette {
+        size: u32,
+    },
+}
+
+// How to fade between the old and new colors when the colors change.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ColorTransition {
+    // In seconds. Zero switches colors immediately.
+    pub duration: f32,
+    pub easing: Easing,
+}
+
+impl Default for ColorTransition {
+    fn default() -> Self {
+        Self {
+            duration: 1.5,
+            easing: Easing::EaseInOut,
+        }
+    }
+}
+
+impl ColorTransition {
+    // How far along the transition is, in [0, 1], after `elapsed` seconds.
+    pub fn progress(&self, elapsed: f32) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+
+        self.easing.apply((elapsed / self.duration).clamp(0.0, 1.0))
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    // Map linear progress in [0, 1] onto the easing curve.
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
         }
     }
 }
@@ -128,8 +528,121 @@ impl ColorPreset {
         match self {
             ColorPreset::Plasma => Some(COLOR_SCHEME_PLASMA),
             ColorPreset::Poolside => Some(COLOR_SCHEME_POOLSIDE),
-            _ => None,
+            ColorPreset::Freedom => Some(COLOR_SCHEME_FREEDOM),
+            ColorPreset::Original => None,
+        }
+    }
+
+    pub fn to_palette(&self) -> Option<Palette> {
+        self.to_color_wheel()
+            .map(|color_wheel| Palette::from_color_wheel(&color_wheel))
+    }
+}
+
+// A ring of colors used by the color wheel.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Palette {
+    pub stops: Vec<ColorStop>,
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColorStop {
+    // An sRGB color with components in [0, 1].
+    pub color: [f32; 3],
+    // A position on the ring in [0, 1]. Stops without a position are spaced
+    // evenly between their neighbours.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<f32>,
+}
+
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum Interpolation {
+    // Mix the sRGB-encoded colors. This is what the color presets use.
+    Srgb,
+    #[default]
+    LinearRgb,
+    Oklab,
+}
+
+impl From<Interpolation> for u32 {
+    fn from(val: Interpolation) -> Self {
+        match val {
+            Interpolation::Srgb => 0,
+            Interpolation::LinearRgb => 1,
+            Interpolation::Oklab => 2,
+        }
+    }
+}
+
+impl Palette {
+    pub fn from_color_wheel(color_wheel: &[f32; 24]) -> Self {
+        Self {
+            stops: color_wheel
+                .chunks_exact(4)
+                .map(|color| ColorStop {
+                    color: [color[0], color[1], color[2]],
+                    position: None,
+                })
+                .collect(),
+            interpolation: Interpolation::Srgb,
+        }
+    }
+
+    // Pack the stops as [r, g, b, position], sorted by position, for the
+    // place_lines shader.
+    pub fn to_color_stops(&self) -> Vec<[f32; 4]> {
+        let positions = self.resolve_positions();
+        let mut color_stops = self
+            .stops
+            .iter()
+            .zip(positions)
+            .map(|(stop, position)| {
+                let [r, g, b] = stop.color;
+                [r, g, b, position]
+            })
+            .collect::<Vec<_>>();
+        color_stops.sort_by(|a, b| a[3].total_cmp(&b[3]));
+        color_stops
+    }
+
+    // Fill in missing positions, like CSS gradients do, except that the
+    // palette wraps around: the gap after the last positioned stop continues
+    // to the first one.
+    fn resolve_positions(&self) -> Vec<f32> {
+        let count = self.stops.len();
+
+        let mut known = Vec::with_capacity(count);
+        let mut previous = 0.0;
+        for (index, stop) in self.stops.iter().enumerate() {
+            // The first stop starts at 0 by default.
+            let position = stop.position.or((index == 0).then_some(0.0));
+            if let Some(position) = position {
+                // Positions can’t go backwards.
+                previous = position.clamp(previous, 1.0);
+                known.push((index, previous));
+            }
         }
+
+        let mut positions = vec![0.0; count];
+        for (k, &(index, position)) in known.iter().enumerate() {
+            let (next_index, next_position) = match known.get(k + 1) {
+                Some(&next) => next,
+                None => (known[0].0 + count, known[0].1 + 1.0),
+            };
+
+            let steps = (next_index - index) as f32;
+            for step in 0..next_index - index {
+                let t = step as f32 / steps;
+                positions[(index + step) % count] =
+                    (position + t * (next_position - position)) % 1.0;
+            }
+        }
+
+        positions
     }
 }
 
@@ -139,6 +652,92 @@ pub struct Noise {
     pub scale: f32,
     pub multiplier: f32,
     pub offset_increment: f32,
+    #[serde(default)]
+    pub kind: NoiseKind,
+}
+
+// The shape of the noise in a channel
+#[derive(Copy, Clone, Default, Debug, Deserialize, Serialize, PartialEq)]
+pub enum NoiseKind {
+    // A pair of independent simplex noises
+    #[default]
+    Simplex,
+    // The curl of a simplex noise. The flow swirls without piling up anywhere,
+    // which leaves less divergence for the pressure solver to clean up.
+    Curl,
+    // Fractal noise: layers of simplex noise, each `lacunarity` times finer
+    // and `gain` times as strong as the last.
+    Fbm {
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+    },
+    // Simplex noise, looked up at positions pushed around by another simplex
+    // noise. `warp` sets how far, in noise cells.
+    DomainWarped {
+        warp: f32,
+    },
+}
+
+// React to the audio passed to `Flux::feed_audio`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Audio {
+    // The frequency bands to follow, as [low, high] in Hz.
+    pub bands: Vec<[f32; 2]>,
+    // How quickly the band levels rise and fall, in seconds.
+    pub attack: f32,
+    pub release: f32,
+    // How far the change in the spectrum has to rise above its recent average
+    // to count as an onset, like a drum hit.
+    pub onset_threshold: f32,
+    // How long an onset takes to fade out, in seconds.
+    pub onset_decay: f32,
+    pub mappings: Vec<AudioMapping>,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self {
+            // Bass, mids and highs
+            bands: vec![[20.0, 250.0], [250.0, 2000.0], [2000.0, 8000.0]],
+            attack: 0.02,
+            release: 0.3,
+            onset_threshold: 1.5,
+            onset_decay: 0.25,
+            mappings: Vec::new(),
+        }
+    }
+}
+
+// Scale a setting by an audio level.
+//
+// The level is in [0, 1], and the setting is multiplied by
+// `1 + amount * level`. Negative amounts turn the setting down instead.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioMapping {
+    pub source: AudioSource,
+    pub target: AudioTarget,
+    pub amount: f32,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum AudioSource {
+    // The level of one of the `bands`
+    Band(usize),
+    // Jumps to 1 on an onset, then fades out
+    Onset,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub enum AudioTarget {
+    NoiseMultiplier,
+    // The `multiplier` of one of the `noiseChannels`
+    NoiseChannelMultiplier(usize),
+    LineWidth,
+    // How fast color transitions play
+    ColorTransitionSpeed,
 }
 
 #[rustfmt::skip]
@@ -151,6 +750,16 @@ pub static COLOR_SCHEME_PLASMA: [f32; 24] = [
     135.291 / 255.0, 152.793 / 255.0, 182.473 / 255.0, 1.0,
 ];
 
+#[rustfmt::skip]
+pub static COLOR_SCHEME_FREEDOM: [f32; 24] = [
+    0.0 / 255.0,   87.0 / 255.0,  183.0 / 255.0, 1.0, // blue
+    0.0 / 255.0,   87.0 / 255.0,  183.0 / 255.0, 1.0, // blue
+    0.0 / 255.0,   87.0 / 255.0,  183.0 / 255.0, 1.0, // blue
+    1.0,           215.0 / 255.0, 0.0,           1.0, // yellow
+    1.0,           215.0 / 255.0, 0.0,           1.0, // yellow
+    1.0,           215.0 / 255.0, 0.0,           1.0, // yellow
+];
+
 #[rustfmt::skip]
 pub static COLOR_SCHEME_POOLSIDE: [f32; 24] = [
     76.0 / 255.0, 156.0 / 255.0, 228.0 / 255.0, 1.0,
@@ -160,3 +769,216 @@ pub static COLOR_SCHEME_POOLSIDE: [f32; 24] = [
     124.0 / 255.0, 220.0 / 255.0, 236.0 / 255.0, 1.0,
     156.0 / 255.0, 208.0 / 255.0, 236.0 / 255.0, 1.0,
 ];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_settings_are_valid() {
+        assert_eq!(Settings::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        let cases = [
+            (
+                Settings {
+                    grid_spacing: 0,
+                    ..Default::default()
+                },
+                "gridSpacing",
+                ValidationErrorKind::Zero,
+            ),
+            (
+                Settings {
+                    fluid_size: 100,
+                    ..Default::default()
+                },
+                "fluidSize",
+                ValidationErrorKind::NotAMultipleOf(16),
+            ),
+            (
+                Settings {
+                    fluid_timestep: 0.0,
+                    ..Default::default()
+                },
+                "fluidTimestep",
+                ValidationErrorKind::NotPositive,
+            ),
+            (
+                Settings {
+                    viscosity: f32::NAN,
+                    ..Default::default()
+                },
+                "viscosity",
+                ValidationErrorKind::NotFinite,
+            ),
+            (
+                Settings {
+                    color_transition: ColorTransition {
+                        duration: -1.0,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                "colorTransition.duration",
+                ValidationErrorKind::Negative,
+            ),
+            (
+                Settings {
+                    noise_channels: vec![],
+                    ..Default::default()
+                },
+                "noiseChannels",
+                ValidationErrorKind::Empty,
+            ),
+            (
+                Settings {
+                    noise_channels: vec![Noise {
+                        kind: NoiseKind::Fbm {
+                            octaves: 12,
+                            lacunarity: 2.0,
+                            gain: 0.5,
+                        },
+                        ..Settings::default().noise_channels[0].clone()
+                    }],
+                    ..Default::default()
+                },
+                "noiseChannels[0].kind.Fbm.octaves",
+                ValidationErrorKind::TooLarge(MAX_NOISE_OCTAVES),
+            ),
+            (
+                Settings {
+                    vorticity_strength: -1.0,
+                    ..Default::default()
+                },
+                "vorticityStrength",
+                ValidationErrorKind::Negative,
+            ),
+            (
+                Settings {
+                    obstacle: Some(Obstacle {
+                        scale: 0.0,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                "obstacle.scale",
+                ValidationErrorKind::NotPositive,
+            ),
+            (
+                Settings {
+                    audio: Audio {
+                        mappings: vec![AudioMapping {
+                            source: AudioSource::Onset,
+                            target: AudioTarget::NoiseChannelMultiplier(3),
+                            amount: 1.0,
+                        }],
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                "audio.mappings[0].target.NoiseChannelMultiplier",
+                ValidationErrorKind::OutOfRange(3),
+            ),
+        ];
+
+        for (settings, field, kind) in cases {
+            assert_eq!(
+                settings.validate(),
+                Err(ValidationError::new(field, kind)),
+                "{}",
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn eases_color_transitions() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ] {
+            assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+            assert_eq!(easing.apply(1.0), 1.0, "{:?}", easing);
+        }
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+
+        let transition = ColorTransition {
+            duration: 2.0,
+            easing: Easing::Linear,
+        };
+        assert_eq!(transition.progress(1.0), 0.5);
+        assert_eq!(transition.progress(3.0), 1.0);
+
+        let instant = ColorTransition {
+            duration: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(instant.progress(0.0), 1.0);
+    }
+
+    fn stop(position: Option<f32>) -> ColorStop {
+        ColorStop {
+            color: [0.0, 0.0, 0.0],
+            position,
+        }
+    }
+
+    fn positions(stops: Vec<ColorStop>) -> Vec<f32> {
+        Palette {
+            stops,
+            interpolation: Interpolation::Oklab,
+        }
+        .to_color_stops()
+        .iter()
+        .map(|stop| stop[3])
+        .collect()
+    }
+
+    #[test]
+    fn spaces_palette_stops_evenly() {
+        assert_eq!(
+            positions(vec![stop(None), stop(None), stop(None), stop(None)]),
+            [0.0, 0.25, 0.5, 0.75]
+        );
+    }
+
+    #[test]
+    fn fills_in_missing_palette_positions() {
+        assert_eq!(
+            positions(vec![
+                stop(Some(0.25)),
+                stop(None),
+                stop(Some(0.5)),
+                stop(None)
+            ]),
+            [0.25, 0.375, 0.5, 0.875]
+        );
+        // The gap after the last positioned stop wraps around to the first.
+        assert_eq!(
+            positions(vec![stop(None), stop(Some(0.5)), stop(None)]),
+            [0.0, 0.5, 0.75]
+        );
+        // Positions can’t go backwards.
+        assert_eq!(
+            positions(vec![stop(Some(0.5)), stop(Some(0.1))]),
+            [0.5, 0.5]
+        );
+    }
+
+    #[test]
+    fn names_the_noise_channel() {
+        let mut settings = Settings::default();
+        settings.noise_channels[1].scale = f32::INFINITY;
+        let err = settings.validate().unwrap_err();
+        assert_eq!(err.field, "noiseChannels[1].scale");
+        assert_eq!(
+            err.to_string(),
+            "Invalid setting `noiseChannels[1].scale`: must be a finite number"
+        );
+    }
+}
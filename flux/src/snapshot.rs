@@ -0,0 +1,516 @@
+// Save and restore the full state of a simulation.
+//
+// A snapshot holds everything needed to resume a simulation exactly where it
+// left off: the fluid textures, the line buffers, the noise offsets, the
+// animation timers, the shared RNG, the queued impulses, the color transition
+// and the audio analyzer.
+//
+// Anything the host can provide again isn’t captured: the settings, the
+// obstacle image, and colors sampled from an image. For the same reason, a
+// transition away from image colors isn’t captured either, as that would mean
+// storing the whole image. Restoring one skips to the end of the transition.
+//
+// Snapshots are stored as little-endian binary files:
+//
+//   magic      "FLUXSNAP"
+//   version    u32
+//   ...        the fields of `Snapshot`, in order
+//
+// Byte arrays are prefixed with their length as a u64. GPU data is stored as is.
+
+use crate::render::fluid::Impulse;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"FLUXSNAP";
+const VERSION: u32 = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Problem {
+    #[error("Failed to read or write the snapshot: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Not a Flux snapshot")]
+    NotASnapshot,
+
+    #[error("Unsupported snapshot version {0}. Expected version {VERSION}")]
+    UnsupportedVersion(u32),
+
+    #[error("The snapshot doesn’t match the simulation: the {what} is {snapshot}, but the simulation’s is {current}")]
+    Mismatch {
+        what: &'static str,
+        snapshot: String,
+        current: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub(crate) fluid: FluidState,
+    pub(crate) lines: LineState,
+    pub(crate) noise: NoiseState,
+    pub(crate) elapsed_time: f32,
+    pub(crate) fluid_frame_time: f32,
+    pub(crate) rng_state: [u64; 2],
+    pub(crate) impulses: Vec<Impulse>,
+    pub(crate) audio: Option<AudioState>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FluidState {
+    pub size: [u32; 2],
+    pub velocity: Vec<u8>,
+    pub pressure: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct LineState {
+    pub line_count: u32,
+    // The buffer holding the latest lines
+    pub frame_num: u32,
+    pub line_buffers: [Vec<u8>; 2],
+    // The noise offsets and blend factor in `LineUniforms`
+    pub noise_offsets: [f32; 3],
+    pub color_transition: ColorTransitionState,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ColorTransitionState {
+    pub time: f32,
+    pub blend: f32,
+    pub previous_color_mode: u32,
+    pub previous_color_interpolation: u32,
+    // The palette the lines are fading from, if any
+    pub previous_color_stops: Vec<[f32; 4]>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AudioState {
+    pub sample_rate: u32,
+    pub pending: Vec<f32>,
+    pub previous_spectrum: Vec<f32>,
+    pub peaks: Vec<f32>,
+    pub flux_average: f32,
+    pub time_since_onset: f32,
+    pub bands: Vec<f32>,
+    pub onset: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct NoiseState {
+    pub elapsed_time: f32,
+    // The offsets and blend factor of each channel
+    pub channels: Vec<[f32; 3]>,
+}
+
+impl Snapshot {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Problem> {
+        let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Problem> {
+        let mut reader = io::BufReader::new(std::fs::File::open(path)?);
+        Self::read_from(&mut reader)
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> Result<(), Problem> {
+        writer.write_all(MAGIC)?;
+        write_u32(writer, VERSION)?;
+
+        write_u32s(writer, &self.fluid.size)?;
+        write_bytes(writer, &self.fluid.velocity)?;
+        write_bytes(writer, &self.fluid.pressure)?;
+
+        write_u32(writer, self.lines.line_count)?;
+        write_u32(writer, self.lines.frame_num)?;
+        for line_buffer in &self.lines.line_buffers {
+            write_bytes(writer, line_buffer)?;
+        }
+        write_f32s(writer, &self.lines.noise_offsets)?;
+        let color_transition = &self.lines.color_transition;
+        write_f32(writer, color_transition.time)?;
+        write_f32(writer, color_transition.blend)?;
+        write_u32(writer, color_transition.previous_color_mode)?;
+        write_u32(writer, color_transition.previous_color_interpolation)?;
+        write_f32_arrays(writer, &color_transition.previous_color_stops)?;
+
+        write_f32(writer, self.noise.elapsed_time)?;
+        write_u32(writer, self.noise.channels.len() as u32)?;
+        for channel in &self.noise.channels {
+            write_f32s(writer, channel)?;
+        }
+
+        write_f32(writer, self.elapsed_time)?;
+        write_f32(writer, self.fluid_frame_time)?;
+        for value in self.rng_state {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        let impulses = self
+            .impulses
+            .iter()
+            .map(|impulse| {
+                let [x, y] = impulse.position;
+                let [vx, vy] = impulse.velocity;
+                let [rx, ry] = impulse.radius;
+                [x, y, vx, vy, rx, ry]
+            })
+            .collect::<Vec<_>>();
+        write_f32_arrays(writer, &impulses)?;
+
+        match &self.audio {
+            None => write_u32(writer, 0)?,
+            Some(audio) => {
+                write_u32(writer, 1)?;
+                write_u32(writer, audio.sample_rate)?;
+                write_f32_vec(writer, &audio.pending)?;
+                write_f32_vec(writer, &audio.previous_spectrum)?;
+                write_f32_vec(writer, &audio.peaks)?;
+                write_f32(writer, audio.flux_average)?;
+                write_f32(writer, audio.time_since_onset)?;
+                write_f32_vec(writer, &audio.bands)?;
+                write_f32(writer, audio.onset)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, Problem> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Problem::NotASnapshot);
+        }
+
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(Problem::UnsupportedVersion(version));
+        }
+
+        let fluid = FluidState {
+            size: [read_u32(reader)?, read_u32(reader)?],
+            velocity: read_bytes(reader)?,
+            pressure: read_bytes(reader)?,
+        };
+
+        let lines = LineState {
+            line_count: read_u32(reader)?,
+            frame_num: read_u32(reader)?,
+            line_buffers: [read_bytes(reader)?, read_bytes(reader)?],
+            noise_offsets: read_f32s(reader)?,
+            color_transition: ColorTransitionState {
+                time: read_f32(reader)?,
+                blend: read_f32(reader)?,
+                previous_color_mode: read_u32(reader)?,
+                previous_color_interpolation: read_u32(reader)?,
+                previous_color_stops: read_f32_arrays(reader)?,
+            },
+        };
+
+        let noise_elapsed_time = read_f32(reader)?;
+        let channel_count = read_u32(reader)?;
+        let noise = NoiseState {
+            elapsed_time: noise_elapsed_time,
+            channels: (0..channel_count)
+                .map(|_| read_f32s(reader))
+                .collect::<Result<_, _>>()?,
+        };
+
+        let elapsed_time = read_f32(reader)?;
+        let fluid_frame_time = read_f32(reader)?;
+        let rng_state = [read_u64(reader)?, read_u64(reader)?];
+
+        let impulses = read_f32_arrays(reader)?
+            .into_iter()
+            .map(|[x, y, vx, vy, rx, ry]| Impulse {
+                position: [x, y],
+                velocity: [vx, vy],
+                radius: [rx, ry],
+            })
+            .collect();
+
+        let audio = match read_u32(reader)? {
+            0 => None,
+            _ => Some(AudioState {
+                sample_rate: read_u32(reader)?,
+                pending: read_f32_vec(reader)?,
+                previous_spectrum: read_f32_vec(reader)?,
+                peaks: read_f32_vec(reader)?,
+                flux_average: read_f32(reader)?,
+                time_since_onset: read_f32(reader)?,
+                bands: read_f32_vec(reader)?,
+                onset: read_f32(reader)?,
+            }),
+        };
+
+        Ok(Self {
+            fluid,
+            lines,
+            noise,
+            elapsed_time,
+            fluid_frame_time,
+            rng_state,
+            impulses,
+            audio,
+        })
+    }
+}
+
+// Check that a part of the snapshot fits the current simulation.
+pub(crate) fn check<T: PartialEq + std::fmt::Debug>(
+    what: &'static str,
+    snapshot: T,
+    current: T,
+) -> Result<(), Problem> {
+    if snapshot == current {
+        Ok(())
+    } else {
+        Err(Problem::Mismatch {
+            what,
+            snapshot: format!("{:?}", snapshot),
+            current: format!("{:?}", current),
+        })
+    }
+}
+
+fn write_u32(writer: &mut impl Write, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_u32s(writer: &mut impl Write, values: &[u32]) -> io::Result<()> {
+    values
+        .iter()
+        .try_for_each(|value| write_u32(writer, *value))
+}
+
+fn write_f32(writer: &mut impl Write, value: f32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_f32s(writer: &mut impl Write, values: &[f32]) -> io::Result<()> {
+    values
+        .iter()
+        .try_for_each(|value| write_f32(writer, *value))
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+// Stored as a byte array, so that they share its length checks.
+fn write_f32_vec(writer: &mut impl Write, values: &[f32]) -> io::Result<()> {
+    let bytes = values
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect::<Vec<_>>();
+    write_bytes(writer, &bytes)
+}
+
+fn write_f32_arrays<const N: usize>(
+    writer: &mut impl Write,
+    arrays: &[[f32; N]],
+) -> io::Result<()> {
+    write_f32_vec(writer, arrays.as_flattened())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_f32s<const N: usize>(reader: &mut impl Read) -> io::Result<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = read_f32(reader)?;
+    }
+    Ok(values)
+}
+
+fn read_bytes(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    // Read through `take`, so a corrupt length can’t allocate unbounded memory.
+    reader.by_ref().take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+fn read_f32_vec(reader: &mut impl Read) -> io::Result<Vec<f32>> {
+    let bytes = read_bytes(reader)?;
+    if bytes.len() % 4 != 0 {
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect())
+}
+
+fn read_f32_arrays<const N: usize>(reader: &mut impl Read) -> io::Result<Vec<[f32; N]>> {
+    let values = read_f32_vec(reader)?;
+    if values.len() % N != 0 {
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+    Ok(values
+        .chunks_exact(N)
+        .map(|chunk| chunk.try_into().unwrap())
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_snapshot() -> Snapshot {
+        Snapshot {
+            fluid: FluidState {
+                size: [2, 1],
+                velocity: (0..16).collect(),
+                pressure: (0..8).collect(),
+            },
+            lines: LineState {
+                line_count: 1,
+                frame_num: 1,
+                line_buffers: [vec![1; 64], vec![2; 64]],
+                noise_offsets: [0.5, 0.25, 0.125],
+                color_transition: ColorTransitionState {
+                    time: 0.5,
+                    blend: 0.25,
+                    previous_color_mode: 1,
+                    previous_color_interpolation: 2,
+                    previous_color_stops: vec![[0.0, 0.5, 1.0, 0.0], [1.0, 0.5, 0.0, 1.0]],
+                },
+            },
+            noise: NoiseState {
+                elapsed_time: 12.5,
+                channels: vec![[1.0, 2.0, 0.5], [3.0, 0.0, 0.0]],
+            },
+            elapsed_time: 42.0,
+            fluid_frame_time: 0.01,
+            rng_state: [u64::MAX, 7],
+            impulses: vec![Impulse {
+                position: [0.5, 0.5],
+                velocity: [1.0, -1.0],
+                radius: [0.1, 0.2],
+            }],
+            audio: Some(AudioState {
+                sample_rate: 44100,
+                pending: vec![0.5; 3],
+                previous_spectrum: vec![0.25; 4],
+                peaks: vec![1.0, 0.5],
+                flux_average: 0.125,
+                time_since_onset: 2.0,
+                bands: vec![0.75, 0.5],
+                onset: 0.5,
+            }),
+        }
+    }
+
+    #[test]
+    fn reads_back_written_snapshots() {
+        let snapshot = test_snapshot();
+        let mut bytes = Vec::new();
+        snapshot.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            Snapshot::read_from(&mut bytes.as_slice()).unwrap(),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        let mut bytes = Vec::new();
+        test_snapshot().write_to(&mut bytes).unwrap();
+
+        let mut other_version = bytes.clone();
+        other_version[8..12].copy_from_slice(&1u32.to_le_bytes());
+        assert!(matches!(
+            Snapshot::read_from(&mut other_version.as_slice()),
+            Err(Problem::UnsupportedVersion(1))
+        ));
+
+        assert!(matches!(
+            Snapshot::read_from(&mut &b"PNG"[..]),
+            Err(Problem::Io(_))
+        ));
+        assert!(matches!(
+            Snapshot::read_from(&mut &b"NOTFLUX!\x01\x00\x00\x00"[..]),
+            Err(Problem::NotASnapshot)
+        ));
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(
+            Snapshot::read_from(&mut bytes.as_slice()),
+            Err(Problem::Io(_))
+        ));
+    }
+
+    #[test]
+    fn restores_the_rng() {
+        crate::rng::init_from_seed(&Some("snapshot".to_string()));
+        let state = crate::rng::state();
+        let expected = crate::rng::gen::<u64>();
+
+        crate::rng::set_state(state);
+        assert_eq!(crate::rng::gen::<u64>(), expected);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU or lavapipe"]
+    fn resumes_the_simulation_exactly() {
+        use crate::headless::test_device;
+        use crate::{Headless, Settings};
+        use std::sync::Arc;
+
+        let (device, queue) = test_device();
+
+        let settings = Arc::new(Settings {
+            seed: Some("snapshot".to_string()),
+            ..Default::default()
+        });
+        let mut headless = Headless::new(&device, &queue, 200, 150, 200, 150, &settings).unwrap();
+        // Whole milliseconds, so that every frame gets exactly the same timestep.
+        let animate = |headless: &mut Headless, frames: std::ops::Range<u32>| {
+            frames
+                .map(|frame| headless.animate(&device, &queue, f64::from(16 * frame)))
+                .last()
+                .unwrap()
+        };
+
+        animate(&mut headless, 0..10);
+        let mut bytes = Vec::new();
+        headless
+            .flux()
+            .snapshot(&device, &queue)
+            .write_to(&mut bytes)
+            .unwrap();
+        let expected = animate(&mut headless, 10..15);
+
+        let snapshot = Snapshot::read_from(&mut bytes.as_slice()).unwrap();
+        headless
+            .flux_mut()
+            .restore(&device, &queue, &snapshot)
+            .unwrap();
+        assert_eq!(animate(&mut headless, 15..20), expected);
+    }
+}
@@ -0,0 +1,253 @@
+// Golden-image regression tests.
+//
+// Render a set of looks for a fixed number of deterministic steps on a software
+// adapter, like Mesa’s lavapipe, and compare the frames against the reference
+// PNGs in `tests/golden`. Software adapters render the same way on every
+// machine, unlike the GPUs we ship on.
+//
+// Frames may differ slightly from the references, as long as the differences
+// are imperceptible: a pixel counts as different if its OKLab distance to the
+// reference is above `PIXEL_TOLERANCE`, and a frame fails if more than
+// `MAX_DIFFERENT_PIXELS` of its pixels are different. Failing frames are written
+// to `target/tmp/golden`, next to a diff image that highlights the differences
+// in red.
+//
+// These need a software adapter, so they’re ignored by default. The manual
+// "Test" workflow runs them on lavapipe, and can write the references on that
+// same setup. To run them locally:
+//
+//   cargo test -p flux --test golden -- --include-ignored
+//
+// To update the references after an intentional visual change, run:
+//
+//   FLUX_BLESS=1 cargo test -p flux --test golden -- --include-ignored
+
+use flux::settings::{ColorMode, ColorPreset, Mode};
+use flux::{Headless, Settings};
+
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 200;
+const STEPS: u32 = 120;
+const TIMESTEP: f32 = 1.0 / 60.0;
+
+// Roughly a just-noticeable difference in OKLab.
+const PIXEL_TOLERANCE: f32 = 0.02;
+const MAX_DIFFERENT_PIXELS: f32 = 0.002;
+
+struct Case {
+    name: &'static str,
+    settings: Settings,
+}
+
+fn cases() -> Vec<Case> {
+    let settings = |seed: &str| Settings {
+        seed: Some(seed.to_string()),
+        ..Default::default()
+    };
+    let preset = |seed: &str, preset: ColorPreset| Settings {
+        color_mode: ColorMode::Preset(preset),
+        ..settings(seed)
+    };
+
+    vec![
+        Case {
+            name: "original",
+            settings: settings("golden"),
+        },
+        Case {
+            name: "original_other_seed",
+            settings: settings("another golden"),
+        },
+        Case {
+            name: "plasma",
+            settings: preset("golden", ColorPreset::Plasma),
+        },
+        Case {
+            name: "poolside",
+            settings: preset("golden", ColorPreset::Poolside),
+        },
+        Case {
+            name: "freedom",
+            settings: preset("golden", ColorPreset::Freedom),
+        },
+        Case {
+            name: "dense_thin_lines",
+            settings: Settings {
+                grid_spacing: 10,
+                line_width: 4.0,
+                line_length: 300.0,
+                ..settings("golden")
+            },
+        },
+        Case {
+            name: "zoomed_in",
+            settings: Settings {
+                view_scale: 1.6,
+                ..settings("golden")
+            },
+        },
+        Case {
+            name: "debug_fluid",
+            settings: Settings {
+                mode: Mode::DebugFluid,
+                ..settings("golden")
+            },
+        },
+    ]
+}
+
+#[test]
+#[ignore = "needs a software adapter, like lavapipe"]
+fn matches_reference_frames() {
+    let (device, queue) = pollster::block_on(Headless::request_device(true))
+        .unwrap_or_else(|err| panic!("The golden-image tests need a software adapter: {}", err));
+
+    let bless = std::env::var_os("FLUX_BLESS").is_some();
+    let reference_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let output_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    if bless {
+        std::fs::create_dir_all(&reference_dir).unwrap();
+    }
+    std::fs::create_dir_all(&output_dir).unwrap();
+
+    let mut failures = Vec::new();
+
+    for case in cases() {
+        let frame = render(&device, &queue, &case.settings);
+        let reference_path = reference_dir.join(format!("{}.png", case.name));
+
+        if bless {
+            frame.save(&reference_path).unwrap();
+            eprintln!("Wrote {}", reference_path.display());
+            continue;
+        }
+
+        let reference = match image::open(&reference_path) {
+            Ok(reference) => reference.to_rgba8(),
+            Err(err) => {
+                save_failure(&frame, None, &output_dir, case.name);
+                failures.push(format!(
+                    "{}: can’t open the reference {}: {}. Run with FLUX_BLESS=1 to write it.",
+                    case.name,
+                    reference_path.display(),
+                    err
+                ));
+                continue;
+            }
+        };
+        if let Err(message) = compare(&frame, &reference, &output_dir, case.name) {
+            failures.push(format!("{}: {}", case.name, message));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Frames don’t match the references:\n{}\nSee {} for the frames and diffs.",
+        failures.join("\n"),
+        output_dir.display()
+    );
+}
+
+fn render(device: &wgpu::Device, queue: &wgpu::Queue, settings: &Settings) -> RgbaImage {
+    let settings = Arc::new(settings.clone());
+    let mut headless = Headless::new(device, queue, WIDTH, HEIGHT, WIDTH, HEIGHT, &settings)
+        .expect("Failed to create the headless renderer");
+
+    for _ in 0..STEPS {
+        headless.step(device, queue, TIMESTEP);
+    }
+
+    headless.render(device, queue)
+}
+
+fn compare(
+    frame: &RgbaImage,
+    reference: &RgbaImage,
+    output_dir: &Path,
+    name: &str,
+) -> Result<(), String> {
+    if frame.dimensions() != reference.dimensions() {
+        save_failure(frame, None, output_dir, name);
+        return Err(format!(
+            "the frame is {:?}, but the reference is {:?}",
+            frame.dimensions(),
+            reference.dimensions()
+        ));
+    }
+
+    let mut diff = RgbaImage::new(frame.width(), frame.height());
+    let mut different_pixels = 0;
+    let mut max_distance: f32 = 0.0;
+
+    for ((pixel, reference_pixel), diff_pixel) in frame
+        .pixels()
+        .zip(reference.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let distance = oklab_distance(pixel, reference_pixel);
+        max_distance = max_distance.max(distance);
+
+        *diff_pixel = if distance > PIXEL_TOLERANCE {
+            different_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // Dim the reference, so the differences stand out.
+            let [r, g, b, _] = reference_pixel.0;
+            let luma =
+                (0.2126 * f32::from(r) + 0.7152 * f32::from(g) + 0.0722 * f32::from(b)) / 4.0;
+            Rgba([luma as u8, luma as u8, luma as u8, 255])
+        };
+    }
+
+    let pixel_count = (frame.width() * frame.height()) as f32;
+    let different_fraction = different_pixels as f32 / pixel_count;
+    if different_fraction > MAX_DIFFERENT_PIXELS {
+        save_failure(frame, Some(&diff), output_dir, name);
+        return Err(format!(
+            "{:.2}% of the pixels are different (max distance {:.3})",
+            100.0 * different_fraction,
+            max_distance
+        ));
+    }
+
+    Ok(())
+}
+
+fn save_failure(frame: &RgbaImage, diff: Option<&RgbaImage>, output_dir: &Path, name: &str) {
+    let path = |suffix: &str| -> PathBuf { output_dir.join(format!("{}.{}.png", name, suffix)) };
+    frame.save(path("actual")).unwrap();
+    if let Some(diff) = diff {
+        diff.save(path("diff")).unwrap();
+    }
+}
+
+fn oklab_distance(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let [l1, a1, b1] = srgb_to_oklab(a);
+    let [l2, a2, b2] = srgb_to_oklab(b);
+    ((l1 - l2).powi(2) + (a1 - a2).powi(2) + (b1 - b2).powi(2)).sqrt()
+}
+
+fn srgb_to_oklab(pixel: &Rgba<u8>) -> [f32; 3] {
+    let [r, g, b] = [pixel.0[0], pixel.0[1], pixel.0[2]].map(|channel| {
+        let c = f32::from(channel) / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+
+    let l = (0.41222147 * r + 0.53633254 * g + 0.05144599 * b).cbrt();
+    let m = (0.2119035 * r + 0.6806995 * g + 0.10739696 * b).cbrt();
+    let s = (0.08830246 * r + 0.28171884 * g + 0.6299787 * b).cbrt();
+
+    [
+        0.21045426 * l + 0.7936178 * m - 0.004072047 * s,
+        1.9779985 * l - 2.4285922 * m + 0.4505937 * s,
+        0.025904037 * l + 0.78277177 * m - 0.80867577 * s,
+    ]
+}
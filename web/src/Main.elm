@@ -73,6 +73,7 @@ type Mode
     | DebugFluid
     | DebugPressure
     | DebugDivergence
+    | DebugVorticity
 
 
 type PressureMode
@@ -748,6 +749,7 @@ viewDebug mode =
         , ( "Fluid", DebugFluid )
         , ( "Pressure", DebugPressure )
         , ( "Divergence", DebugDivergence )
+        , ( "Vorticity", DebugVorticity )
         ]
     ]
 
@@ -901,6 +903,9 @@ encodeMode mode =
             DebugDivergence ->
                 "DebugDivergence"
 
+            DebugVorticity ->
+                "DebugVorticity"
+
 
 encodePressureMode : PressureMode -> Encode.Value
 encodePressureMode pressureMode =
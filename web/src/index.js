@@ -31,6 +31,17 @@ function setupFlux() {
     });
     resizeObserver.observe(document.getElementById("canvas"));
 
+    // The WebGL2 backend can’t be stirred.
+    if (flux.add_impulse) {
+      stirOnDrag(document.getElementById("canvas"));
+    }
+
+    // Browsers only allow audio after a click, so wait for one.
+    // Open with `?audio` to react to the microphone.
+    if (flux.feed_audio && new URLSearchParams(window.location.search).has("audio")) {
+      window.addEventListener("click", listenToMicrophone, { once: true });
+    }
+
     window.requestAnimationFrame(animate);
   });
 
@@ -41,6 +52,12 @@ function setupFlux() {
         .then(bitmap => flux.save_image(bitmap));
     }
 
+    if (newSettings.obstacle) {
+      fetch(newSettings.obstacle.image)
+        .then(response => response.arrayBuffer())
+        .then(buffer => flux.set_obstacle_image(new Uint8Array(buffer)));
+    }
+
     flux.settings = newSettings;
   });
 }
@@ -52,3 +69,72 @@ async function loadImage(imageUrl) {
   const blob = await response.blob();
   return createImageBitmap(blob, { resizeWidth: 500, resizeHeight: 500 });
 }
+
+// How far a drag stirs the fluid, in CSS pixels.
+const DRAG_RADIUS = 60;
+
+// Stir the fluid with mouse drags and touches. Each pointer keeps track of its
+// last position, so several fingers can stir at once.
+function stirOnDrag(canvas) {
+  const pointers = new Map();
+  canvas.style.touchAction = "none";
+
+  canvas.addEventListener("pointerdown", (event) => {
+    canvas.setPointerCapture(event.pointerId);
+    pointers.set(event.pointerId, event);
+  });
+
+  canvas.addEventListener("pointermove", (event) => {
+    const last = pointers.get(event.pointerId);
+    if (!last) {
+      return;
+    }
+
+    const elapsed = Math.max(event.timeStamp - last.timeStamp, 1) / 1000;
+    const bounds = canvas.getBoundingClientRect();
+    flux.add_impulse(
+      event.clientX - bounds.left,
+      event.clientY - bounds.top,
+      (event.clientX - last.clientX) / elapsed,
+      (event.clientY - last.clientY) / elapsed,
+      DRAG_RADIUS,
+    );
+    pointers.set(event.pointerId, event);
+  });
+
+  for (const type of ["pointerup", "pointercancel"]) {
+    canvas.addEventListener(type, (event) => pointers.delete(event.pointerId));
+  }
+}
+
+// Pass the first channel of each audio block on to the main thread.
+const AUDIO_PROCESSOR = `
+  registerProcessor("flux-audio", class extends AudioWorkletProcessor {
+    process([input]) {
+      if (input.length > 0) {
+        this.port.postMessage(input[0].slice());
+      }
+      return true;
+    }
+  });
+`;
+
+// Feed the microphone to Flux, so that it can react to the music in the room.
+async function listenToMicrophone() {
+  const stream = await navigator.mediaDevices.getUserMedia({ audio: true });
+  const context = new AudioContext();
+  const processorUrl = URL.createObjectURL(
+    new Blob([AUDIO_PROCESSOR], { type: "application/javascript" }),
+  );
+  await context.audioWorklet.addModule(processorUrl);
+
+  const source = context.createMediaStreamSource(stream);
+  // Without outputs, the node runs without being connected to the speakers.
+  const processor = new AudioWorkletNode(context, "flux-audio", {
+    numberOfOutputs: 0,
+  });
+  processor.port.onmessage = (event) => {
+    flux.feed_audio(event.data, context.sampleRate);
+  };
+  source.connect(processor);
+}